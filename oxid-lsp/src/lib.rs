@@ -1,10 +1,8 @@
 use std::sync::atomic::AtomicUsize;
 
-use crate::types::*;
-
+pub mod capabilities;
 pub mod client;
 pub mod types;
-pub mod capabilities;
 
 static ID: AtomicUsize = AtomicUsize::new(1);
 
@@ -1,19 +1,28 @@
 use std::collections::HashMap;
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{Receiver, Sender};
 
 use oxid_lsp::client::LspClient;
 use oxid_lsp::types::{CompletionItem, CompletionList, Diagnostic, Hover};
 use ratatui::widgets::TableState;
 
 use crate::buffer::Buffer;
+use crate::buffer::types::{BlockInsert, BufferPosition};
 use crate::config::Config;
 use crate::events::EventKind;
+use crate::keymap::LastChange;
+use crate::registers::Registers;
+use crate::search::Search;
 use crate::ui::ui;
 
+mod actions;
 mod commands;
+mod cursors;
 mod events;
 mod lsp;
+mod marks;
 pub mod modes;
+mod search;
+mod substitute;
 
 pub struct App {
     pub mode: modes::Mode,
@@ -22,8 +31,34 @@ pub struct App {
     pub quitting: bool,
     pub buffers: Vec<Buffer>,
     pub current_buf_index: usize,
-    pub registers: HashMap<String, String>,
+    pub registers: Registers,
+    /// Register given to the action being run ("a, "+, ...), if any.
+    pub register: Option<char>,
     pub command: Option<String>,
+    pub pending_keys: String,
+    pub block_insert: Option<BlockInsert>,
+    pub last_change: Option<LastChange>,
+    /// Whether the current Insert session belongs to the last change and is being recorded.
+    pub recording_change: bool,
+    pub repeating_change: bool,
+    /// Register a macro is being recorded into, along with the keys recorded so far.
+    pub recording_macro: Option<char>,
+    pub macro_keys: String,
+    pub last_macro: Option<char>,
+    /// How many macros are being played, one inside another.
+    pub macro_depth: usize,
+    /// Last search, used by "n" / "N" and highlighted in the editor.
+    pub search: Option<Search>,
+    pub search_forward: bool,
+    /// Where the cursor and the last search were when the search prompt was opened.
+    pub search_origin: Option<(BufferPosition, Option<Search>)>,
+    pub show_search_highlight: bool,
+    pub confirm_substitute: Option<substitute::ConfirmSubstitute>,
+    /// "A"-"Z" marks, they remember their file.
+    pub global_marks: HashMap<char, marks::GlobalMark>,
+    pub jumplist: Vec<marks::Jump>,
+    /// Position in the jumplist while walking it, its length otherwise.
+    pub jump_index: usize,
     pub lsp_client: Option<LspClient>,
     pub diagnostics: Option<Vec<Diagnostic>>,
     pub show_diagnostics: bool,
@@ -34,16 +69,18 @@ pub struct App {
     pub hover: Option<Hover>,
     pub error: Option<String>,
     pub debug_mode: bool,
+    pub config: Config,
+    /// Sends the chunks of large files loaded in the background.
+    pub event_sender: Option<Sender<EventKind>>,
 }
 
 impl App {
-    pub fn new(buffers: Vec<Buffer>, tsize_x: usize, tsize_y: usize, config: Config) -> Self {
-        let file_type = buffers[0]
-            .file_path
-            .as_ref()
-            .expect("First file should have a path")
-            .split(".")
-            .last();
+    pub fn new(mut buffers: Vec<Buffer>, tsize_x: usize, tsize_y: usize, config: Config) -> Self {
+        for buffer in &mut buffers {
+            buffer.settings = config.filetype_config(buffer.filetype());
+        }
+        // Large files are not sent to the LSP server.
+        let file_type = buffers[0].filetype().filter(|_| !buffers[0].large_file);
 
         let mut client = if let Some(ftype) = file_type {
             config
@@ -75,8 +112,26 @@ impl App {
             quitting: false,
             buffers,
             current_buf_index: 0,
-            registers: HashMap::from([(String::from("default"), String::new())]),
+            registers: Registers::new(),
+            register: None,
             command: None,
+            pending_keys: String::new(),
+            block_insert: None,
+            last_change: None,
+            recording_change: false,
+            repeating_change: false,
+            recording_macro: None,
+            macro_keys: String::new(),
+            last_macro: None,
+            macro_depth: 0,
+            search: None,
+            search_forward: true,
+            search_origin: None,
+            show_search_highlight: true,
+            confirm_substitute: None,
+            global_marks: HashMap::new(),
+            jumplist: Vec::new(),
+            jump_index: 0,
             lsp_client: client,
             diagnostics: None,
             show_diagnostics: true,
@@ -86,7 +141,9 @@ impl App {
             table_state: TableState::default().with_selected(0),
             hover: None,
             debug_mode: false,
+            config,
             error: None,
+            event_sender: None,
         }
     }
 
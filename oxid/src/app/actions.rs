@@ -0,0 +1,513 @@
+use std::ops::Range;
+
+use ratatui::DefaultTerminal;
+
+use super::App;
+use super::modes::Mode;
+use crate::buffer::types::{
+    BlockInsert, BufferPosition, Motion, Selection, SelectionKind, TextObject,
+};
+use crate::buffer::{Buffer, at_each_cursor};
+use crate::events::EventKind;
+use crate::keymap::{Action, Inserted, Operator};
+
+/// Limit for macros playing other macros, so that one calling itself ends.
+const MAX_MACRO_DEPTH: usize = 100;
+
+impl App {
+    pub fn execute_action(
+        &mut self,
+        action: Action,
+        count: Option<usize>,
+        terminal: &mut DefaultTerminal,
+    ) {
+        let vis = self.mode.is_visual();
+        // With extra cursors, moves and changes happen at each of them. The cursors are
+        // taken out of the buffer meanwhile, so this runs the action once per cursor.
+        if !vis
+            && !self.buffers[self.current_buf_index].cursors.is_empty()
+            && (action.is_change() || matches!(action, Action::Move(_)))
+        {
+            at_each_cursor(self, App::current_buffer, |app| {
+                app.execute_action(action.clone(), count, terminal);
+            });
+            return;
+        }
+        let times = count.unwrap_or(1);
+        match action {
+            Action::Move(motion) => {
+                if motion.is_jump() {
+                    self.push_jump();
+                }
+                self.buffers[self.current_buf_index].apply_motion_count(motion, count);
+                if vis {
+                    self.update_visual_selection();
+                }
+            }
+            Action::Operate(operator, motion) => {
+                self.operate_with_motion(operator, motion, count, terminal)
+            }
+            Action::OperateObject(operator, object) => {
+                let buffer = &self.buffers[self.current_buf_index];
+                if let Some(range) = buffer.text_object_range(object) {
+                    if let TextObject::Paragraph { .. } = object {
+                        // Paragraphs are made of whole lines, so they are operated linewise.
+                        let first = buffer.file_text.char_to_line(range.start);
+                        let last = buffer.file_text.char_to_line(range.end.max(1) - 1);
+                        self.operate_on_lines(operator, first, last, terminal);
+                    } else {
+                        self.operate_on_range(operator, range, terminal);
+                    }
+                }
+            }
+            Action::SelectObject(object) => self.select_object(object),
+            Action::OperateLines(operator) => {
+                let line = self.buffers[self.current_buf_index].current_position.line;
+                self.operate_on_lines(operator, line, line + times - 1, terminal);
+            }
+            Action::OperateSelection(operator) => self.operate_on_selection(operator, terminal),
+            Action::ToggleDiagnostics => self.show_diagnostics = !self.show_diagnostics,
+            Action::CommandMode => self.set_mode(terminal, Mode::Command),
+            Action::VisualMode => self.set_mode(terminal, Mode::Visual),
+            Action::VisualLineMode => self.set_mode(terminal, Mode::VisualLine),
+            Action::InsertLineStart | Action::AppendLineEnd if self.mode == Mode::VisualBlock => {
+                self.start_block_insert(action == Action::AppendLineEnd, terminal)
+            }
+            Action::Insert => self.set_mode(terminal, Mode::Insert),
+            Action::ReplaceMode => self.set_mode(terminal, Mode::Replace),
+            Action::ReplaceChar(ch) => {
+                self.buffers[self.current_buf_index].replace_chars(ch, times);
+                self.notify_did_change();
+            }
+            Action::JoinLines { normalize } => {
+                let buffer = &mut self.buffers[self.current_buf_index];
+                // "3J" joins three lines, "J" and a single line selection join two.
+                let (first, last) = match &buffer.selection {
+                    Some(selection) => {
+                        let (first, last, _, _) = selection.bounds(buffer.numbar_space);
+                        (first, last.max(first + 1))
+                    }
+                    None => {
+                        let line = buffer.current_position.line;
+                        (line, line + times.max(2) - 1)
+                    }
+                };
+                self.leave_visual(terminal);
+                self.buffers[self.current_buf_index].join_lines(first, last, normalize);
+                self.notify_did_change();
+            }
+            Action::ToggleCase => {
+                self.buffers[self.current_buf_index].toggle_case_at_cursor(times);
+                self.notify_did_change();
+            }
+            Action::InsertLineBelow => {
+                self.buffers[self.current_buf_index].insert_line_below();
+                self.set_mode(terminal, Mode::Insert);
+            }
+            Action::InsertLineStart => {
+                self.leave_visual(terminal);
+                self.buffers[self.current_buf_index].move_cursor_start_line();
+                self.set_mode(terminal, Mode::Insert);
+            }
+            Action::AppendLineEnd => {
+                self.leave_visual(terminal);
+                self.buffers[self.current_buf_index].move_cursor_end_line();
+                self.set_mode(terminal, Mode::Insert);
+            }
+            Action::Paste | Action::PasteBefore => {
+                if let Some(register) = self.registers.get(self.register) {
+                    let text = register.text.repeat(times);
+                    let linewise = register.linewise;
+                    self.buffers[self.current_buf_index].paste(
+                        &text,
+                        linewise,
+                        action == Action::PasteBefore,
+                    );
+                    self.notify_did_change();
+                }
+            }
+            Action::Undo => {
+                for _ in 0..times {
+                    self.handle_undo();
+                }
+            }
+            Action::Repeat => self.repeat_last_change(count, terminal),
+            Action::Search { forward } => self.start_search(forward, terminal),
+            Action::SearchNext { reverse } => {
+                self.search_next(reverse, times);
+                if vis {
+                    self.update_visual_selection();
+                }
+            }
+            Action::SearchWord { forward } => {
+                self.search_word(forward, times);
+                if vis {
+                    self.update_visual_selection();
+                }
+            }
+            Action::RecordMacro(name) => {
+                self.recording_macro = Some(name);
+                self.macro_keys.clear();
+            }
+            Action::PlayMacro(name) => self.play_macro(name, times, terminal),
+            Action::SplitSelection => self.split_selection(terminal),
+            Action::SetMark(mark) => self.set_mark(mark),
+            Action::JumpToMark { mark, exact } => {
+                // Global marks can be in another buffer, the selection can't follow them.
+                if mark.is_ascii_uppercase() {
+                    self.leave_visual(terminal);
+                }
+                self.jump_to_mark(mark, exact);
+                if self.mode.is_visual() {
+                    self.update_visual_selection();
+                }
+            }
+            Action::Hover => {
+                if self.hover.is_none() {
+                    self.hover();
+                } else {
+                    self.hover = None;
+                }
+            }
+        }
+    }
+
+    fn current_buffer(&mut self) -> &mut Buffer {
+        &mut self.buffers[self.current_buf_index]
+    }
+
+    /// Replays the last change at the cursor, a count given to "." replaces its own.
+    fn repeat_last_change(&mut self, count: Option<usize>, terminal: &mut DefaultTerminal) {
+        let Some(change) = self.last_change.clone() else {
+            return;
+        };
+        self.repeating_change = true;
+        self.register = change.register;
+        self.execute_action(change.action, count.or(change.count), terminal);
+        if self.mode == Mode::Insert || self.mode == Mode::Replace {
+            for inserted in change.inserted {
+                match inserted {
+                    Inserted::Key(event) => {
+                        if let Err(err) = self.handle_event(event, terminal) {
+                            self.error = Some(err.to_string());
+                        }
+                    }
+                    Inserted::Completion(label) => {
+                        self.insert_completion(&label);
+                        self.notify_did_change();
+                    }
+                }
+            }
+            self.handle_normal_mode(terminal);
+        }
+        self.repeating_change = false;
+    }
+
+    fn play_macro(&mut self, name: char, times: usize, terminal: &mut DefaultTerminal) {
+        let Some(name) = (if name == '@' {
+            self.last_macro
+        } else {
+            Some(name)
+        }) else {
+            return;
+        };
+        let Some(register) = self.registers.get(Some(name)) else {
+            return;
+        };
+        if self.macro_depth >= MAX_MACRO_DEPTH {
+            return;
+        }
+
+        let events: Vec<EventKind> = register.text.chars().map(EventKind::from_key).collect();
+        self.last_macro = Some(name);
+        self.macro_depth += 1;
+        for _ in 0..times {
+            for event in events.iter().cloned() {
+                if let Err(err) = self.handle_event(event, terminal) {
+                    self.error = Some(err.to_string());
+                }
+            }
+        }
+        self.macro_depth -= 1;
+    }
+
+    fn operate_with_motion(
+        &mut self,
+        operator: Operator,
+        motion: Motion,
+        count: Option<usize>,
+        terminal: &mut DefaultTerminal,
+    ) {
+        let times = count.unwrap_or(1);
+        let buffer = &mut self.buffers[self.current_buf_index];
+        let start_pos = buffer.current_position.clone();
+        let start = buffer.cursor_char_idx();
+
+        // Like in vim, "cw" on a word only changes until the end of that word.
+        if operator == Operator::Change
+            && motion == Motion::NextWord
+            && let Some(mut word_end) = buffer.word_end_at_cursor()
+        {
+            if times > 1 {
+                buffer.current_position = buffer.char_idx_to_position(word_end - 1);
+                for _ in 1..times {
+                    buffer.apply_motion(Motion::EndOfWord);
+                }
+                word_end = buffer.cursor_char_idx() + 1;
+                buffer.current_position = start_pos;
+            }
+            self.operate_on_range(operator, start..word_end, terminal);
+            return;
+        }
+
+        buffer.apply_motion_count(motion, count);
+        let end_pos = buffer.current_position.clone();
+        let mut end = buffer.cursor_char_idx();
+        buffer.current_position = start_pos.clone();
+
+        // Like in vim, "dtx" does nothing when there's no "x" to find.
+        if motion.is_char_search() && end == start {
+            return;
+        }
+
+        if motion.is_linewise() {
+            let first = start_pos.line.min(end_pos.line);
+            let last = start_pos.line.max(end_pos.line);
+            self.operate_on_lines(operator, first, last, terminal);
+            return;
+        }
+
+        // A word motion that jumps to another line, or can't move past the last word, only
+        // operates until the end of this line.
+        if motion == Motion::NextWord && (end_pos.line > start_pos.line || end <= start) {
+            end = buffer.file_text.line_to_char(start_pos.line)
+                + buffer.line_content_len(start_pos.line);
+        }
+
+        let (from, mut to) = (start.min(end), start.max(end));
+        if motion.is_inclusive() || (motion.is_char_search() && end > start) {
+            to = (to + 1).min(buffer.file_text.len_chars());
+        }
+        self.operate_on_range(operator, from..to, terminal);
+    }
+
+    fn select_object(&mut self, object: TextObject) {
+        let buffer = &mut self.buffers[self.current_buf_index];
+        if let Some(range) = buffer.text_object_range(object) {
+            let start = buffer.char_idx_to_position(range.start);
+            let end = buffer.char_idx_to_position(range.end);
+            buffer.current_position = end.clone();
+            buffer.selection = Some(Selection {
+                start,
+                end,
+                kind: SelectionKind::Char,
+            });
+            buffer.update_selected_string();
+            buffer.ensure_cursor_visible();
+            self.mode = Mode::Visual;
+        }
+    }
+
+    pub(super) fn leave_visual(&mut self, terminal: &mut DefaultTerminal) {
+        if self.mode.is_visual() {
+            let buffer = &mut self.buffers[self.current_buf_index];
+            buffer.selection = None;
+            buffer.selected_string = None;
+            self.set_mode(terminal, Mode::Normal);
+        }
+    }
+
+    /// Starts inserting ("I") or appending ("A") on every line of the visual block.
+    fn start_block_insert(&mut self, append: bool, terminal: &mut DefaultTerminal) {
+        let buffer = &mut self.buffers[self.current_buf_index];
+        let Some(selection) = buffer.selection.clone() else {
+            return;
+        };
+        let (first_line, last_line, first_col, last_col) = selection.bounds(buffer.numbar_space);
+        let column = if append { last_col + 1 } else { first_col };
+        self.leave_visual(terminal);
+        self.begin_block_insert(first_line, last_line, column, append, terminal);
+    }
+
+    fn begin_block_insert(
+        &mut self,
+        first_line: usize,
+        last_line: usize,
+        column: usize,
+        append: bool,
+        terminal: &mut DefaultTerminal,
+    ) {
+        let buffer = &mut self.buffers[self.current_buf_index];
+        let mut line_len = buffer.line_content_len(first_line);
+        if line_len < column {
+            let line_end = buffer.file_text.line_to_char(first_line) + line_len;
+            buffer.insert_text(line_end, &" ".repeat(column - line_len));
+            line_len = column;
+        }
+        buffer.current_position = BufferPosition {
+            line: first_line,
+            character: column + buffer.numbar_space,
+        };
+        buffer.ensure_cursor_visible();
+        self.block_insert = Some(BlockInsert {
+            first_line,
+            last_line,
+            column,
+            line_len,
+            len_lines: buffer.file_text.len_lines(),
+            append,
+        });
+        self.set_mode(terminal, Mode::Insert);
+    }
+
+    fn operate_on_selection(&mut self, operator: Operator, terminal: &mut DefaultTerminal) {
+        let buffer = &mut self.buffers[self.current_buf_index];
+        let Some(selection) = buffer.selection.clone() else {
+            return;
+        };
+        let (first_line, last_line, first_col, _) = selection.bounds(buffer.numbar_space);
+        let start = buffer.position_to_char_idx(&selection.start);
+        let end = buffer.position_to_char_idx(&selection.end);
+        let block = buffer.block_ranges();
+        let block_text = buffer.selected_string.clone().unwrap_or_default();
+
+        buffer.selection = None;
+        buffer.selected_string = None;
+        self.set_mode(terminal, Mode::Normal);
+        match selection.kind {
+            SelectionKind::Char => {
+                self.operate_on_range(operator, start.min(end)..start.max(end), terminal)
+            }
+            SelectionKind::Line => self.operate_on_lines(operator, first_line, last_line, terminal),
+            SelectionKind::Block => self.operate_on_block(
+                operator,
+                block,
+                block_text,
+                (first_line, last_line, first_col),
+                terminal,
+            ),
+        }
+    }
+
+    fn operate_on_block(
+        &mut self,
+        operator: Operator,
+        rows: Vec<Range<usize>>,
+        text: String,
+        (first_line, last_line, first_col): (usize, usize, usize),
+        terminal: &mut DefaultTerminal,
+    ) {
+        if matches!(
+            operator,
+            Operator::Indent | Operator::Dedent | Operator::Comment
+        ) {
+            self.operate_on_lines(operator, first_line, last_line, terminal);
+            return;
+        }
+
+        let buffer = &mut self.buffers[self.current_buf_index];
+        if operator == Operator::Yank {
+            self.registers.yank(self.register, text, false);
+        } else if let Operator::Case(case) = operator {
+            for row in rows.into_iter().rev() {
+                buffer.change_case(row, case);
+            }
+            self.notify_did_change();
+        } else {
+            self.registers.delete(self.register, text, false);
+            // Bottom up, so the ranges of the rows above stay valid.
+            for row in rows.into_iter().rev() {
+                buffer.remove_text(row);
+            }
+            self.notify_did_change();
+        }
+
+        let buffer = &mut self.buffers[self.current_buf_index];
+        let line_start = buffer.file_text.line_to_char(first_line);
+        let column = first_col.min(buffer.line_content_len(first_line));
+        buffer.current_position = buffer.char_idx_to_position(line_start + column);
+        buffer.ensure_cursor_visible();
+        if operator == Operator::Change {
+            self.begin_block_insert(first_line, last_line, first_col, false, terminal);
+        }
+    }
+
+    fn operate_on_range(
+        &mut self,
+        operator: Operator,
+        range: Range<usize>,
+        terminal: &mut DefaultTerminal,
+    ) {
+        let buffer = &mut self.buffers[self.current_buf_index];
+        match operator {
+            Operator::Yank => {
+                let text = buffer.file_text.slice(range.clone()).to_string();
+                self.registers.yank(self.register, text, false);
+                buffer.current_position = buffer.char_idx_to_position(range.start);
+            }
+            Operator::Delete | Operator::Change => {
+                let text = buffer.remove_text(range.clone());
+                self.registers.delete(self.register, text, false);
+                buffer.current_position = buffer.char_idx_to_position(range.start);
+                buffer.ensure_cursor_visible();
+                if operator == Operator::Change {
+                    self.set_mode(terminal, Mode::Insert);
+                }
+                self.notify_did_change();
+            }
+            Operator::Case(case) => {
+                buffer.change_case(range.clone(), case);
+                buffer.current_position = buffer.char_idx_to_position(range.start);
+                buffer.ensure_cursor_visible();
+                self.notify_did_change();
+            }
+            Operator::Indent | Operator::Dedent | Operator::Comment => {
+                let first = buffer.file_text.char_to_line(range.start);
+                let last = buffer
+                    .file_text
+                    .char_to_line(range.end.max(range.start + 1) - 1);
+                self.operate_on_lines(operator, first, last, terminal);
+            }
+        }
+    }
+
+    fn operate_on_lines(
+        &mut self,
+        operator: Operator,
+        first: usize,
+        last: usize,
+        terminal: &mut DefaultTerminal,
+    ) {
+        let buffer = &mut self.buffers[self.current_buf_index];
+        match operator {
+            Operator::Yank => {
+                let text = buffer.lines_text(first, last);
+                self.registers.yank(self.register, text, true);
+                buffer.current_position.line = first;
+            }
+            Operator::Delete => {
+                let text = buffer.delete_lines(first, last);
+                self.registers.delete(self.register, text, true);
+            }
+            Operator::Change => {
+                let text = buffer.clear_lines(first, last);
+                self.registers.delete(self.register, text, true);
+                self.set_mode(terminal, Mode::Insert);
+            }
+            Operator::Indent => buffer.indent_lines(first, last),
+            Operator::Dedent => buffer.dedent_lines(first, last),
+            Operator::Comment => buffer.toggle_comment(first, last),
+            Operator::Case(case) => {
+                let range = buffer.lines_char_range(first, last);
+                buffer.change_case(range, case);
+                buffer.current_position.line = first;
+                let char_idx = buffer.position_to_char_idx(&buffer.current_position);
+                buffer.current_position = buffer.char_idx_to_position(char_idx);
+            }
+        }
+
+        if operator != Operator::Yank {
+            self.notify_did_change();
+        }
+    }
+}
@@ -1,11 +1,19 @@
 use ratatui::DefaultTerminal;
 use ropey::Rope;
 
-use std::{fs::OpenOptions, io::BufReader};
+use std::{
+    fs::OpenOptions,
+    io::{BufReader, Read},
+};
 
 use super::App;
 use crate::buffer::Buffer;
-use crate::{app::modes::Mode, command::Command};
+use crate::encoding::FileFormat;
+use crate::events::load_file_chunks;
+use crate::{
+    app::modes::Mode,
+    command::{Command, SetOption},
+};
 
 impl App {
     pub fn apply_command(&mut self, terminal: &mut DefaultTerminal) {
@@ -37,23 +45,42 @@ impl App {
             Command::GoToLine(line) => self.go_to_line(line, terminal),
             Command::StartLsp(lsp_command) => self.start_lsp(&lsp_command, terminal),
             Command::StopLsp => self.stop_lsp(terminal),
+            Command::Substitute(substitute) => self.substitute(substitute, terminal),
+            Command::NoHighlight => {
+                self.show_search_highlight = false;
+                self.reset_command(terminal);
+            }
+            Command::Set(option) => self.set_option(option, terminal),
         }
     }
 
+    fn set_option(&mut self, option: SetOption, terminal: &mut DefaultTerminal) {
+        let buffer = &mut self.buffers[self.current_buf_index];
+        match option {
+            SetOption::Wrap(wrap) => buffer.settings.wrap = wrap,
+            SetOption::FileFormat(line_ending) => buffer.file_format.line_ending = line_ending,
+            SetOption::FileEncoding(encoding) => buffer.file_format.set_encoding(encoding),
+            SetOption::TabStop(tabstop) => buffer.settings.tabstop = tabstop,
+            SetOption::ShiftWidth(shiftwidth) => buffer.settings.shiftwidth = shiftwidth,
+            SetOption::ExpandTab(expandtab) => buffer.settings.expandtab = expandtab,
+        }
+        buffer.ensure_cursor_visible();
+        self.reset_command(terminal);
+    }
+
     fn start_lsp(&mut self, command: &str, terminal: &mut DefaultTerminal) {
         if self.lsp_client.is_none() {
             let client = oxid_lsp::client::start_lsp(command).ok();
             self.lsp_client = client;
 
-            // We can unwrap because we're garanteed to be Some(LspClient) here.
-            if self.lsp_client.is_some() && self.lsp_client.as_mut().unwrap().initialize().is_ok() {
+            if let Some(lsp) = self.lsp_client.as_mut()
+                && lsp.initialize().is_ok()
+            {
                 for buffer in &self.buffers {
-                    if let Some(buffer_path) = &buffer.file_path {
-                        _ = self
-                            .lsp_client
-                            .as_mut()
-                            .unwrap()
-                            .did_open(buffer_path, &buffer.file_text.to_string());
+                    if let Some(buffer_path) = &buffer.file_path
+                        && !buffer.large_file
+                    {
+                        _ = lsp.did_open(buffer_path, &buffer.file_text.to_string());
                     }
                 }
             }
@@ -72,21 +99,27 @@ impl App {
     }
 
     fn save_current_file(&mut self, terminal: &mut DefaultTerminal) {
-        // TODO: Handle this better
-        self.buffers[self.current_buf_index]
-            .save_file()
-            .expect("Could not save current file.");
         self.set_mode(terminal, Mode::Normal);
         self.command = None;
+        // Large files can't be saved while they load.
+        if self.buffers[self.current_buf_index].loading {
+            self.error = Some(String::from("The file is still loading"));
+            return;
+        }
+        if let Err(err) = self.buffers[self.current_buf_index].save_file() {
+            self.error = Some(err.to_string());
+        }
     }
 
     fn save_all(&mut self, terminal: &mut DefaultTerminal) {
-        self.buffers.iter().for_each(|buf| {
-            // TODO: Handle this better
-            buf.save_file().expect("Could not save all files");
-        });
         self.set_mode(terminal, Mode::Normal);
         self.command = None;
+        for buf in self.buffers.iter_mut().filter(|buf| !buf.loading) {
+            if let Err(err) = buf.save_file() {
+                self.error = Some(err.to_string());
+                return;
+            }
+        }
     }
 
     fn quit_current_file(&mut self, terminal: &mut DefaultTerminal) {
@@ -98,6 +131,8 @@ impl App {
         }
         // -2 because we are gonna remove one more right now, to avoid an extra assign.
         let num_buffers = self.buffers.len() - 2;
+        self.save_global_marks(self.current_buf_index);
+        _ = self.buffers[self.current_buf_index].save_undo_file();
         _ = self.buffers.remove(self.current_buf_index);
         if self.current_buf_index + 1 >= num_buffers {
             self.current_buf_index = 0;
@@ -111,6 +146,7 @@ impl App {
     fn quit_all(&mut self, terminal: &mut DefaultTerminal) {
         self.set_mode(terminal, Mode::Normal);
         self.command = None;
+        self.save_undo_files();
         if let Some(lsp) = self.lsp_client.as_mut() {
             _ = lsp.shutdown();
         }
@@ -119,18 +155,33 @@ impl App {
     fn save_quit_all(&mut self, terminal: &mut DefaultTerminal) {
         self.set_mode(terminal, Mode::Normal);
         self.command = None;
-        self.buffers.iter().for_each(|buf| {
-            // TODO: Handle this better
-            buf.save_file().expect("Could not save all files.");
-        });
+        if self.buffers.iter().any(|buf| buf.loading) {
+            self.error = Some(String::from("A file is still loading"));
+            return;
+        }
+        for buf in &mut self.buffers {
+            // Quitting would lose the changes that couldn't be saved.
+            if let Err(err) = buf.save_file() {
+                self.error = Some(err.to_string());
+                return;
+            }
+        }
         if let Some(lsp) = self.lsp_client.as_mut() {
             _ = lsp.shutdown();
         }
         self.quitting = true;
     }
+    /// Saves the undo history of every buffer, when quitting.
+    pub(super) fn save_undo_files(&self) {
+        for buffer in &self.buffers {
+            _ = buffer.save_undo_file();
+        }
+    }
+
     fn next_buffer(&mut self, terminal: &mut DefaultTerminal) {
         // .len() and not .len() - 1 bc we want only 0 when index would be
         // greater than allowed index (len() - 1).
+        self.push_jump();
         if self.current_buf_index + 1 == self.buffers.len() {
             self.current_buf_index = 0;
         } else {
@@ -141,6 +192,7 @@ impl App {
     }
 
     fn previous_buffer(&mut self, terminal: &mut DefaultTerminal) {
+        self.push_jump();
         if self.current_buf_index as isize - 1 == -1 {
             self.current_buf_index = self.buffers.len() - 1;
         } else {
@@ -152,6 +204,7 @@ impl App {
 
     fn open_file(&mut self, file: String, terminal: &mut DefaultTerminal) {
         if let Some(buffer) = self.create_new_buffer(file) {
+            self.push_jump();
             self.buffers.push(buffer);
             self.current_buf_index = self.buffers.len() - 1;
         }
@@ -160,6 +213,7 @@ impl App {
     }
 
     fn go_to_line(&mut self, line_num: isize, terminal: &mut DefaultTerminal) {
+        self.push_jump();
         let max_buf_lines = self.buffers[self.current_buf_index].file_text.len_lines() - 1;
 
         if line_num == -1 || line_num > max_buf_lines as isize {
@@ -173,19 +227,56 @@ impl App {
         self.command = None;
     }
 
-    fn create_new_buffer(&self, file_path: String) -> Option<Buffer> {
+    pub(super) fn create_new_buffer(&self, file_path: String) -> Option<Buffer> {
+        let large_file = self.config.is_large_file(&file_path);
+        if large_file && let Some(sender) = &self.event_sender {
+            let mut buf = Buffer::new_large_file(file_path.clone(), self.tsize_x, self.tsize_y);
+            buf.settings = self.config.filetype_config(buf.filetype());
+            let sender = sender.clone();
+            std::thread::spawn(move || load_file_chunks(file_path, sender));
+            return Some(buf);
+        }
         if let Ok(file_handler) = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open(&file_path)
         {
-            if let Ok(file_text) = Rope::from_reader(BufReader::new(file_handler)) {
-                let buf = Buffer::new(Some(file_path), file_text, self.tsize_x, self.tsize_y);
+            let mut bytes = Vec::new();
+            if BufReader::new(file_handler).read_to_end(&mut bytes).is_ok() {
+                let (file_text, file_format, lossy) = FileFormat::decode(&bytes);
+                let file_text = Rope::from_str(&file_text);
+                let mut buf = Buffer::new(Some(file_path), file_text, self.tsize_x, self.tsize_y);
+                buf.file_format = file_format;
+                buf.lossy = lossy;
+                buf.settings = self.config.filetype_config(buf.filetype());
+                buf.large_file = large_file;
+                buf.load_undo_file();
                 return Some(buf);
             }
             return None;
         }
         None
     }
+
+    /// Appends a chunk of a large file to its buffer, if it's still open.
+    pub(super) fn append_file_chunk(
+        &mut self,
+        file_path: &str,
+        text: &str,
+        format: FileFormat,
+        lossy: bool,
+        done: bool,
+    ) {
+        if let Some(buffer) = self
+            .buffers
+            .iter_mut()
+            .find(|buffer| buffer.file_path.as_deref() == Some(file_path))
+        {
+            buffer.file_format = format;
+            buffer.lossy = lossy;
+            buffer.append_loaded(text, done);
+        }
+    }
 }
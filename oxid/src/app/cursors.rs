@@ -0,0 +1,54 @@
+use ratatui::DefaultTerminal;
+
+use super::App;
+use crate::buffer::types::{SelectionKind, TextObject};
+use crate::search::Search;
+
+impl App {
+    /// Ctrl+n, adds a cursor at the next occurrence of the word under the cursor, or of the
+    /// selected text in Visual mode. The occurrence becomes the search pattern, so that the
+    /// next Ctrl+n (and "n") look for it again.
+    pub(super) fn add_cursor_next(&mut self, terminal: &mut DefaultTerminal) {
+        let buffer = &mut self.buffers[self.current_buf_index];
+        if self.mode.is_visual() {
+            let Some(selection) = buffer.selection.clone() else {
+                return;
+            };
+            let Some(text) = buffer.selected_string.clone() else {
+                return;
+            };
+            // Matches never span lines, so only a part of a line can be looked for.
+            if selection.kind != SelectionKind::Char
+                || selection.start.line != selection.end.line
+                || text.is_empty()
+            {
+                return;
+            }
+            buffer.current_position.character =
+                selection.start.character.min(selection.end.character);
+            self.search = Search::new(&regex::escape(&text), true);
+            self.leave_visual(terminal);
+        } else if buffer.cursors.is_empty() || self.search.is_none() {
+            let Some(range) = buffer.text_object_range(TextObject::Word { around: false }) else {
+                return;
+            };
+            let word = buffer.file_text.slice(range.clone()).to_string();
+            if !word.chars().any(|c| c.is_alphanumeric() || c == '_') {
+                return;
+            }
+            buffer.current_position = buffer.char_idx_to_position(range.start);
+            self.search = Search::word(&word, true);
+        }
+
+        if let Some(search) = &self.search {
+            self.show_search_highlight = true;
+            self.buffers[self.current_buf_index].add_cursor_at_next_match(&search.regex);
+        }
+    }
+
+    /// "S" in Visual mode, one cursor per selected line.
+    pub(super) fn split_selection(&mut self, terminal: &mut DefaultTerminal) {
+        self.buffers[self.current_buf_index].split_selection();
+        self.leave_visual(terminal);
+    }
+}
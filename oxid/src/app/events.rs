@@ -2,6 +2,7 @@ use ratatui::DefaultTerminal;
 
 use crate::buffer::types::Selection;
 use crate::events::EventKind;
+use crate::keymap::{Action, Inserted, LastChange, Parsed};
 
 use super::App;
 use super::modes::Mode;
@@ -12,7 +13,36 @@ impl App {
         event: EventKind,
         terminal: &mut DefaultTerminal,
     ) -> anyhow::Result<()> {
+        // Keys replayed from a macro are already in the one being recorded.
+        if self.recording_macro.is_some()
+            && self.macro_depth == 0
+            && let Some(key) = event.to_key()
+        {
+            self.macro_keys.push(key);
+        }
+
+        if self.recording_change
+            && (self.mode == Mode::Insert || self.mode == Mode::Replace)
+            && let Some(change) = &mut self.last_change
+        {
+            match event {
+                EventKind::NormalMode | EventKind::FileChunk { .. } => {}
+                // Completions are recorded as the text they insert, when accepted.
+                EventKind::RequestCompletion => {}
+                EventKind::Tab | EventKind::ShiftTab if self.completion_list.is_some() => {}
+                EventKind::EnterKey if self.selected_completion.is_some() => {}
+                _ => change.inserted.push(Inserted::Key(event.clone())),
+            }
+        }
+
         match event {
+            EventKind::FileChunk {
+                file_path,
+                text,
+                format,
+                lossy,
+                done,
+            } => self.append_file_chunk(&file_path, &text, format, lossy, done),
             EventKind::RequestCompletion => self.handle_completion()?,
             EventKind::SaveFile => self.handle_save_file(terminal)?,
             EventKind::Quit => self.handle_quit()?,
@@ -26,6 +56,13 @@ impl App {
             EventKind::Tab => {
                 if self.completion_list.is_some() {
                     self.next_table_row();
+                } else if self.mode == Mode::Normal {
+                    self.pending_keys.clear();
+                    self.jump_forward();
+                } else if self.mode == Mode::Insert {
+                    self.buffers[self.current_buf_index]
+                        .at_each_cursor(|buffer| buffer.insert_tab());
+                    self.notify_did_change();
                 }
             }
             EventKind::ShiftTab => {
@@ -33,32 +70,115 @@ impl App {
                     self.previous_table_row();
                 }
             }
+            EventKind::Redo => self.handle_redo(),
+            EventKind::AddCursorNext => {
+                if self.mode == Mode::Normal || self.mode.is_visual() {
+                    self.pending_keys.clear();
+                    self.add_cursor_next(terminal);
+                }
+            }
+            EventKind::AddCursorBelow | EventKind::AddCursorAbove => {
+                if self.mode == Mode::Normal {
+                    self.pending_keys.clear();
+                    self.buffers[self.current_buf_index]
+                        .add_cursor_vertical(event == EventKind::AddCursorBelow);
+                }
+            }
+            EventKind::JumpBack => {
+                if self.mode == Mode::Normal {
+                    self.pending_keys.clear();
+                    self.jump_back();
+                }
+            }
+            EventKind::VisualBlockMode => {
+                if self.mode == Mode::Normal || self.mode.is_visual() {
+                    self.pending_keys.clear();
+                    self.set_mode(terminal, Mode::VisualBlock);
+                }
+            }
+        }
+
+        // Everything typed during an insert session is a single undoable change,
+        // any other edit gets its own revision as soon as it is done.
+        // A substitution being confirmed and a Replace session are also single changes.
+        if !matches!(self.mode, Mode::Insert | Mode::Replace | Mode::Confirm) {
+            self.buffers[self.current_buf_index].commit_history();
         }
         Ok(())
     }
 
+    /// Feeds a key typed in Normal or Visual mode to the pending key sequence and runs
+    /// the resulting action once the sequence is complete.
+    fn handle_normal_key(&mut self, ch: char, terminal: &mut DefaultTerminal) {
+        if ch == 'q'
+            && self.pending_keys.is_empty()
+            && self.mode == Mode::Normal
+            && let Some(name) = self.recording_macro.take()
+        {
+            // The "q" that stops the recording is not part of the macro.
+            self.macro_keys.pop();
+            let keys = std::mem::take(&mut self.macro_keys);
+            self.registers.record_macro(name, keys);
+            return;
+        }
+
+        self.pending_keys.push(ch);
+        match Action::parse(&self.pending_keys, self.mode.is_visual()) {
+            Parsed::Action(action, count, register) => {
+                self.pending_keys.clear();
+                if action.is_change() && !self.mode.is_visual() && !self.repeating_change {
+                    self.last_change = Some(LastChange {
+                        action: action.clone(),
+                        count,
+                        register,
+                        inserted: Vec::new(),
+                    });
+                    self.recording_change = true;
+                }
+                self.register = register;
+                self.execute_action(action, count, terminal);
+                // Only an Insert or Replace session started by the change is part of it.
+                if !matches!(self.mode, Mode::Insert | Mode::Replace) {
+                    self.recording_change = false;
+                }
+            }
+            Parsed::Pending => {}
+            Parsed::Invalid => self.pending_keys.clear(),
+        }
+    }
+
+    pub(super) fn handle_undo(&mut self) {
+        if self.buffers[self.current_buf_index].undo() {
+            self.notify_did_change();
+        }
+    }
+
+    fn handle_redo(&mut self) {
+        if self.mode == Mode::Normal && self.buffers[self.current_buf_index].redo() {
+            self.notify_did_change();
+        }
+    }
+
     fn handle_completion(&mut self) -> anyhow::Result<()> {
         if self.mode == Mode::Insert
-            && let Some(file_path) = self.buffers[self.current_buf_index].file_path.clone()
+            && let Some(file_path) = self.lsp_file_path()
         {
             let file_contents = self.buffers[self.current_buf_index].file_text.to_string();
 
+            let buffer = &self.buffers[self.current_buf_index];
+            let position = buffer.to_lsp_position(&buffer.current_position);
+
             if let Some(lsp) = self.lsp_client.as_mut() {
                 lsp.did_change(&file_path, &file_contents)?;
 
-                self.completion_list = match lsp.request_completion(
-                    &file_path,
-                    self.buffers[self.current_buf_index].current_position.line,
-                    self.buffers[self.current_buf_index]
-                        .current_position
-                        .character,
-                ) {
-                    Ok(opt) => opt,
-                    Err(err) => {
-                        self.error = Some(err.to_string());
-                        None
-                    }
-                };
+                self.completion_list =
+                    match lsp.request_completion(&file_path, position.line, position.character) {
+                        Ok(opt) => opt,
+                        Err(err) => {
+                            self.error = Some(err.to_string());
+                            None
+                        }
+                    };
             }
             if self.completion_list.is_some() {
                 self.choose_completion(0);
@@ -74,12 +194,10 @@ impl App {
 
     fn handle_save_file(&mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<()> {
         if self.buffers[self.current_buf_index].save_file().is_ok() {
-            let fp = self.buffers[self.current_buf_index]
-                .file_path
-                .clone()
-                .expect("Filepath should be Some(fp)"); // As we're inside Ok, shouldn't fail.
-            let fc = self.buffers[self.current_buf_index].file_text.to_string();
-            if let Some(lsp) = self.lsp_client.as_mut() {
+            if let Some(fp) = self.lsp_file_path()
+                && let Some(lsp) = self.lsp_client.as_mut()
+            {
+                let fc = self.buffers[self.current_buf_index].file_text.to_string();
                 lsp.did_save(&fp, &fc)?;
                 self.get_diagnostics();
             }
@@ -89,6 +207,7 @@ impl App {
     }
 
     fn handle_quit(&mut self) -> anyhow::Result<()> {
+        self.save_undo_files();
         if let Some(lsp) = self.lsp_client.as_mut() {
             _ = lsp.shutdown();
         }
@@ -96,8 +215,26 @@ impl App {
         Ok(())
     }
 
-    fn handle_normal_mode(&mut self, terminal: &mut DefaultTerminal) {
+    pub(super) fn handle_normal_mode(&mut self, terminal: &mut DefaultTerminal) {
+        if self.mode == Mode::Search {
+            self.cancel_search();
+        }
+        if self.mode == Mode::Confirm {
+            self.finish_substitute(terminal);
+        }
+        self.recording_change = false;
+        // Esc in Normal mode drops the extra cursors.
+        if self.mode == Mode::Normal && self.pending_keys.is_empty() {
+            self.buffers[self.current_buf_index].clear_cursors();
+        }
+        if let Some(block) = self.block_insert.take()
+            && self.mode == Mode::Insert
+            && self.buffers[self.current_buf_index].finish_block_insert(&block)
+        {
+            self.notify_did_change();
+        }
         self.set_mode(terminal, Mode::Normal);
+        self.pending_keys.clear();
         self.buffers[self.current_buf_index].selection = None;
         self.buffers[self.current_buf_index].selected_string = None;
         self.completion_offset = 0;
@@ -117,195 +254,105 @@ impl App {
     }
 
     fn handle_key(&mut self, ch: char, terminal: &mut DefaultTerminal) {
-        if self.mode == Mode::Command {
+        if self.mode == Mode::Confirm {
+            self.handle_confirm_key(ch, terminal);
+            return;
+        }
+        if self.mode == Mode::Command || self.mode == Mode::Search {
             if let Some(cmd_str) = &mut self.command {
                 cmd_str.push(ch);
             } else {
                 self.command = Some(String::from(ch));
             }
+            if self.mode == Mode::Search {
+                self.update_search();
+            }
         }
-        if self.mode == Mode::Normal || self.mode == Mode::Visual {
+        if self.mode == Mode::Normal || self.mode.is_visual() {
             self.hover = None;
-            let vis = self.mode == Mode::Visual;
-            match ch {
-                '[' => {
-                    self.show_diagnostics = !self.show_diagnostics;
-                }
-                ':' => self.set_mode(terminal, Mode::Command),
-                'v' => self.set_mode(terminal, Mode::Visual),
-                'h' => {
-                    self.buffers[self.current_buf_index].move_cursor_left();
-                    if vis {
-                        self.update_visual_selection();
-                    }
-                }
-                'j' => {
-                    self.buffers[self.current_buf_index].move_cursor_down();
-                    if vis {
-                        self.update_visual_selection();
-                    }
-                }
-                'k' => {
-                    self.buffers[self.current_buf_index].move_cursor_up();
-                    if vis {
-                        self.update_visual_selection();
-                    }
-                }
-                'l' => {
-                    self.buffers[self.current_buf_index].move_cursor_right();
-                    if vis {
-                        self.update_visual_selection();
-                    }
-                }
-                'w' => {
-                    self.buffers[self.current_buf_index].move_to_next_word();
-                    if vis {
-                        self.update_visual_selection();
-                    }
-                }
-                'b' => {
-                    self.buffers[self.current_buf_index].move_to_previous_word();
-                    if vis {
-                        self.update_visual_selection();
-                    }
-                }
-                'e' => {
-                    self.buffers[self.current_buf_index].move_to_end_of_word();
-                    if vis {
-                        self.update_visual_selection();
-                    }
-                }
-                'i' => {
-                    if !vis {
-                        self.set_mode(terminal, Mode::Insert);
-                    }
-                }
-                'o' => {
-                    if !vis {
-                        self.buffers[self.current_buf_index].insert_line_below();
-                        self.set_mode(terminal, Mode::Insert);
-                    }
-                }
-                '0' => {
-                    self.buffers[self.current_buf_index].move_cursor_start_line();
-                    if vis {
-                        self.update_visual_selection();
-                    }
-                }
-                '$' => {
-                    self.buffers[self.current_buf_index].move_cursor_end_line();
-                    if vis {
-                        self.update_visual_selection();
-                    }
-                }
-                'y' => {
-                    if vis
-                        && let Some(selection) =
-                            &self.buffers[self.current_buf_index].selected_string
-                    {
-                        self.registers
-                            .insert(String::from("default"), selection.to_string());
-                        self.set_mode(terminal, Mode::Normal);
-                        self.buffers[self.current_buf_index].selection = None;
-                    }
-                }
-                'p' => {
-                    if !vis
-                        && let Some(paste_string) = self.registers.get("default")
-                        && !paste_string.is_empty()
-                    {
-                        self.buffers[self.current_buf_index].paste(paste_string.to_owned());
-                    }
-                }
-                _ => {}
-            }
+            self.handle_normal_key(ch, terminal);
         } else if self.mode == Mode::Insert {
-            self.buffers[self.current_buf_index].insert_char(ch);
-            if let Some(fp) = &self.buffers[self.current_buf_index].file_path
-                && let Some(lsp) = self.lsp_client.as_mut()
-            {
-                _ = lsp.did_change(
-                    fp,
-                    &self.buffers[self.current_buf_index].file_text.to_string(),
-                );
-            }
+            self.buffers[self.current_buf_index].at_each_cursor(|buffer| buffer.type_char(ch));
+            self.notify_did_change();
+        } else if self.mode == Mode::Replace {
+            self.buffers[self.current_buf_index].replace_char(ch);
+            self.notify_did_change();
         }
     }
 
     fn handle_shifted_key(&mut self, ch: char, terminal: &mut DefaultTerminal) {
-        if self.mode == Mode::Normal {
-            match ch {
-                'I' => {
-                    self.buffers[self.current_buf_index].move_cursor_start_line();
-                    self.set_mode(terminal, Mode::Insert);
-                }
-                'A' => {
-                    self.buffers[self.current_buf_index].move_cursor_end_line();
-                    self.set_mode(terminal, Mode::Insert);
-                }
-                'K' => {
-                    if self.hover.is_none() {
-                        self.hover();
-                    } else {
-                        self.hover = None;
-                    }
-                }
-                _ => {}
-            }
+        if self.mode == Mode::Normal || self.mode.is_visual() {
+            self.handle_normal_key(ch, terminal);
         } else if self.mode == Mode::Insert && (ch.is_alphanumeric() || ch.is_ascii_punctuation()) {
-            self.buffers[self.current_buf_index].insert_char(ch);
-        } else if self.mode == Mode::Command {
+            self.buffers[self.current_buf_index].at_each_cursor(|buffer| buffer.type_char(ch));
+        } else if self.mode == Mode::Replace {
+            self.buffers[self.current_buf_index].replace_char(ch);
+            self.notify_did_change();
+        } else if self.mode == Mode::Command || self.mode == Mode::Search {
             if let Some(cmd_str) = &mut self.command {
                 cmd_str.push(ch);
             } else {
                 self.command = Some(String::from(ch));
             }
+            if self.mode == Mode::Search {
+                self.update_search();
+            }
         }
     }
 
     fn handle_backspace(&mut self) {
         if self.mode == Mode::Insert {
-            self.buffers[self.current_buf_index].remove_char();
+            self.buffers[self.current_buf_index].at_each_cursor(|buffer| buffer.backspace());
+        } else if self.mode == Mode::Replace {
+            self.buffers[self.current_buf_index].replace_backspace();
+            self.notify_did_change();
         }
-        if self.mode == Mode::Command
+        if (self.mode == Mode::Command || self.mode == Mode::Search)
             && let Some(command) = &mut self.command
         {
             command.pop();
         }
+        if self.mode == Mode::Search {
+            self.update_search();
+        }
     }
 
     fn handle_enter(&mut self, terminal: &mut DefaultTerminal) {
         if self.mode == Mode::Insert {
             if let Some(completion_item) = &self.selected_completion {
-                let buffer_pos = self.buffers[self.current_buf_index].get_viewport_cursor_pos();
-                self.insert_completion(completion_item.clone(), buffer_pos);
+                let label = completion_item.label.clone();
+                self.insert_completion(&label);
+                if self.recording_change
+                    && let Some(change) = &mut self.last_change
+                {
+                    change.inserted.push(Inserted::Completion(label));
+                }
                 self.selected_completion = None;
                 self.completion_list = None;
                 self.completion_offset = 0;
 
-                if let Some(lsp) = self.lsp_client.as_mut() {
-                    let fp = self.buffers[self.current_buf_index]
-                        .file_path
-                        .clone()
-                        .unwrap_or_else(|| String::from(""));
-                    let contents = self.buffers[self.current_buf_index].file_text.to_string();
-                    let _ = lsp.did_change(&fp, &contents);
-                }
+                self.notify_did_change();
             } else {
-                self.buffers[self.current_buf_index].enter_key();
+                self.buffers[self.current_buf_index].at_each_cursor(|buffer| buffer.enter());
             }
+        } else if self.mode == Mode::Replace {
+            self.buffers[self.current_buf_index].replace_enter();
+            self.notify_did_change();
         } else if self.mode == Mode::Command {
             self.apply_command(terminal);
+        } else if self.mode == Mode::Search {
+            self.confirm_search(terminal);
         }
     }
 
-    fn update_visual_selection(&mut self) {
+    pub(super) fn update_visual_selection(&mut self) {
         if let Some(selection) = &self.buffers[self.current_buf_index].selection {
             let start = selection.start.clone();
+            let kind = selection.kind;
             let end = self.buffers[self.current_buf_index]
                 .current_position
                 .clone();
-            self.buffers[self.current_buf_index].selection = Some(Selection { start, end });
+            self.buffers[self.current_buf_index].selection = Some(Selection { start, end, kind });
             self.buffers[self.current_buf_index].update_selected_string();
         }
     }
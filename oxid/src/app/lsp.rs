@@ -1,15 +1,29 @@
-use oxid_lsp::types::{CompletionItem, Position};
-
-use crate::buffer::types::BufferPosition;
-
 use super::App;
 
 impl App {
+    /// Path of the current buffer for the LSP server. Large files are never sent to it.
+    pub(super) fn lsp_file_path(&self) -> Option<String> {
+        let buffer = &self.buffers[self.current_buf_index];
+        buffer.file_path.clone().filter(|_| !buffer.large_file)
+    }
+
+    /// Sends the whole contents of the current buffer to the LSP server, if any.
+    pub fn notify_did_change(&mut self) {
+        if let Some(fp) = self.lsp_file_path()
+            && let Some(lsp) = self.lsp_client.as_mut()
+        {
+            _ = lsp.did_change(
+                &fp,
+                &self.buffers[self.current_buf_index].file_text.to_string(),
+            );
+        }
+    }
+
     pub fn get_diagnostics(&mut self) {
-        if let Some(filepath) = &self.buffers[self.current_buf_index].file_path
+        if let Some(filepath) = self.lsp_file_path()
             && let Some(lsp) = self.lsp_client.as_mut()
         {
-            match lsp.get_file_diagnostic(filepath) {
+            match lsp.get_file_diagnostic(&filepath) {
                 Ok(diag_opt) => match diag_opt {
                     Some(diag_vec) => self.diagnostics = Some(diag_vec),
                     None => self.diagnostics = None,
@@ -20,24 +34,13 @@ impl App {
     }
 
     pub fn hover(&mut self) {
-        if let Some(curr_file_path) = &self.buffers[self.current_buf_index].file_path
+        if let Some(curr_file_path) = self.lsp_file_path()
             && let Some(lsp) = self.lsp_client.as_mut()
         {
-            let mut pos = self.buffers[self.current_buf_index]
-                .current_position
-                .clone();
-
-            pos.character = pos
-                .character
-                .saturating_sub(self.buffers[self.current_buf_index].numbar_space);
-
-            match lsp.hover(
-                curr_file_path,
-                Position {
-                    line: pos.line,
-                    character: pos.character,
-                },
-            ) {
+            let buffer = &self.buffers[self.current_buf_index];
+            let position = buffer.to_lsp_position(&buffer.current_position);
+
+            match lsp.hover(&curr_file_path, position) {
                 Ok(hover) => self.hover = Some(hover),
                 Err(_) => self.hover = None,
             }
@@ -105,8 +108,10 @@ impl App {
         }
     }
 
-    pub fn insert_completion(&mut self, completion: CompletionItem, buffer_pos: BufferPosition) {
+    /// Replaces the identifier before the cursor with the label of a completion.
+    pub fn insert_completion(&mut self, label: &str) {
         let buffer = &mut self.buffers[self.current_buf_index];
+        let buffer_pos = buffer.current_position.clone();
         let line_start_idx = buffer.file_text.line_to_char(buffer_pos.line);
         let mut start_idx = line_start_idx + buffer_pos.character - buffer.numbar_space;
 
@@ -129,13 +134,49 @@ impl App {
 
         let end_idx = line_start_idx + buffer_pos.character - buffer.numbar_space;
 
-        let start_byte = buffer.file_text.char_to_byte(start_idx);
-        let end_byte = buffer.file_text.char_to_byte(end_idx);
-
-        buffer.file_text.remove(start_byte..end_byte);
-        buffer.file_text.insert(start_byte, &completion.label);
+        buffer.remove_text(start_idx..end_idx);
+        buffer.insert_text(start_idx, label);
 
         buffer.current_position.character =
-            start_idx + completion.label.chars().count() - line_start_idx + buffer.numbar_space;
+            start_idx + label.chars().count() - line_start_idx + buffer.numbar_space;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use crate::app::App;
+    use crate::buffer::Buffer;
+    use crate::config::Config;
+
+    #[test]
+    fn test_insert_completion_after_tabs() {
+        let buffer = Buffer::new(None, Rope::from_str("\t\tfoo\n"), 80, 24);
+        let mut app = App::new(vec![buffer], 80, 24, Config::default());
+        let buffer = &mut app.buffers[0];
+        buffer.settings.tabstop = 8;
+        buffer.current_position.character = 5 + buffer.numbar_space;
+
+        app.insert_completion("foobar");
+        let buffer = &app.buffers[0];
+        assert_eq!(buffer.file_text.to_string(), "\t\tfoobar\n");
+        assert_eq!(buffer.current_position.character, 8 + buffer.numbar_space);
+    }
+
+    #[test]
+    fn test_insert_completion_below_wrapped_line() {
+        let text = format!("{}\n    foo\n", "x".repeat(200));
+        let buffer = Buffer::new(None, Rope::from_str(&text), 80, 24);
+        let mut app = App::new(vec![buffer], 80, 24, Config::default());
+        let buffer = &mut app.buffers[0];
+        buffer.settings.wrap = true;
+        buffer.current_position.line = 1;
+        buffer.current_position.character = 7 + buffer.numbar_space;
+
+        app.insert_completion("foobar");
+        let buffer = &app.buffers[0];
+        assert_eq!(buffer.file_text.line(1).to_string(), "    foobar\n");
+        assert_eq!(buffer.current_position.character, 10 + buffer.numbar_space);
     }
 }
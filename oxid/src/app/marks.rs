@@ -0,0 +1,196 @@
+use super::App;
+
+/// A mark set with "m{A-Z}", it can take you back to another file.
+#[derive(Debug, Clone)]
+pub struct GlobalMark {
+    pub file_path: String,
+    /// Where the mark was when its buffer was last closed. While the buffer is open, the
+    /// mark kept (and shifted) by the buffer itself is used instead.
+    pub char_idx: usize,
+}
+
+/// A position in the jumplist, walked with Ctrl+o / Ctrl+i.
+#[derive(Debug, Clone)]
+pub struct Jump {
+    pub file_path: Option<String>,
+    pub char_idx: usize,
+}
+
+const MAX_JUMPS: usize = 100;
+
+impl App {
+    /// "m{mark}", lowercase marks belong to the buffer and uppercase ones are global.
+    pub(super) fn set_mark(&mut self, mark: char) {
+        let char_idx = self.buffers[self.current_buf_index].cursor_char_idx();
+        if mark.is_ascii_uppercase() {
+            let Some(file_path) = self.buffers[self.current_buf_index].file_path.clone() else {
+                return;
+            };
+            for buffer in &mut self.buffers {
+                buffer.marks.remove(&mark);
+            }
+            self.global_marks.insert(
+                mark,
+                GlobalMark {
+                    file_path,
+                    char_idx,
+                },
+            );
+        }
+        self.buffers[self.current_buf_index].set_mark(mark, char_idx);
+    }
+
+    /// Line of a mark in the current buffer, for Ex ranges like "'a,'b".
+    pub(super) fn mark_line(&self, mark: char) -> Option<usize> {
+        let buffer = &self.buffers[self.current_buf_index];
+        match mark {
+            '<' | '>' => {
+                let selection = buffer.last_selection.as_ref()?;
+                let (first, last, _, _) = selection.bounds(buffer.numbar_space);
+                Some(if mark == '<' { first } else { last })
+            }
+            _ => Some(buffer.file_text.char_to_line(buffer.mark(mark)?)),
+        }
+    }
+
+    /// "'{mark}" jumps to the first non-blank of the mark line, "`{mark}" to the mark itself.
+    pub(super) fn jump_to_mark(&mut self, mark: char, exact: bool) {
+        let Some((buf_index, char_idx)) = self.find_mark(mark) else {
+            return;
+        };
+        self.push_jump();
+        self.current_buf_index = buf_index;
+
+        let buffer = &mut self.buffers[buf_index];
+        buffer.current_position = buffer.char_idx_to_position(char_idx);
+        if !exact {
+            let line = buffer.current_position.line;
+            buffer.current_position.character = buffer.first_non_blank(line) + buffer.numbar_space;
+        }
+        buffer.ensure_cursor_visible();
+    }
+
+    /// Buffer index and char index of a mark, opening the file of a global mark if needed.
+    fn find_mark(&mut self, mark: char) -> Option<(usize, usize)> {
+        let buffer = &self.buffers[self.current_buf_index];
+        match mark {
+            'A'..='Z' => {
+                let global = self.global_marks.get(&mark)?.clone();
+                if let Some(buf_index) = self.buffer_index(Some(&global.file_path)) {
+                    let char_idx = self.buffers[buf_index].mark(mark)?;
+                    return Some((buf_index, char_idx));
+                }
+                let mut buffer = self.create_new_buffer(global.file_path)?;
+                buffer.set_mark(mark, global.char_idx);
+                self.buffers.push(buffer);
+                Some((self.buffers.len() - 1, global.char_idx))
+            }
+            '<' | '>' => {
+                let selection = buffer.last_selection.as_ref()?;
+                let (first, last) = if selection.start.line < selection.end.line
+                    || (selection.start.line == selection.end.line
+                        && selection.start.character <= selection.end.character)
+                {
+                    (&selection.start, &selection.end)
+                } else {
+                    (&selection.end, &selection.start)
+                };
+                let position = if mark == '<' { first } else { last };
+                Some((
+                    self.current_buf_index,
+                    buffer.position_to_char_idx(position),
+                ))
+            }
+            // "''" and "``" go back to where the last jump was made from.
+            '`' => Some((self.current_buf_index, buffer.mark('\'')?)),
+            _ => Some((self.current_buf_index, buffer.mark(mark)?)),
+        }
+    }
+
+    /// Keeps the uppercase marks of a buffer that's about to be closed.
+    pub(super) fn save_global_marks(&mut self, buf_index: usize) {
+        let buffer = &self.buffers[buf_index];
+        for (mark, global) in self.global_marks.iter_mut() {
+            if buffer.file_path.as_ref() == Some(&global.file_path)
+                && let Some(char_idx) = buffer.mark(*mark)
+            {
+                global.char_idx = char_idx;
+            }
+        }
+    }
+
+    fn buffer_index(&self, file_path: Option<&String>) -> Option<usize> {
+        if file_path.is_none() {
+            return Some(self.current_buf_index);
+        }
+        self.buffers
+            .iter()
+            .position(|buffer| buffer.file_path.as_ref() == file_path)
+    }
+
+    /// Records the cursor position in the jumplist, to be called right before a big jump
+    /// (go to line, search, buffer switch, ...). Also sets the "'" mark.
+    pub fn push_jump(&mut self) {
+        let buffer = &mut self.buffers[self.current_buf_index];
+        let char_idx = buffer.cursor_char_idx();
+        buffer.set_mark('\'', char_idx);
+
+        // Only the newest jump of each line is kept.
+        let line = buffer.current_position.line;
+        let buffer = &self.buffers[self.current_buf_index];
+        self.jumplist.retain(|jump| {
+            jump.file_path != buffer.file_path
+                || buffer
+                    .file_text
+                    .char_to_line(jump.char_idx.min(buffer.file_text.len_chars()))
+                    != line
+        });
+        self.jumplist.push(Jump {
+            file_path: buffer.file_path.clone(),
+            char_idx,
+        });
+        if self.jumplist.len() > MAX_JUMPS {
+            self.jumplist.remove(0);
+        }
+        self.jump_index = self.jumplist.len();
+    }
+
+    /// Ctrl+o, goes back to an older position of the jumplist.
+    pub(super) fn jump_back(&mut self) {
+        if self.jump_index >= self.jumplist.len() {
+            // Remember where we are, so that Ctrl+i can come back here.
+            self.push_jump();
+            self.jump_index = self.jumplist.len() - 1;
+        }
+        if self.jump_index > 0 {
+            self.go_to_jump(self.jump_index - 1);
+        }
+    }
+
+    /// Ctrl+i (or Tab), goes forward to a newer position of the jumplist.
+    pub(super) fn jump_forward(&mut self) {
+        if self.jump_index + 1 < self.jumplist.len() {
+            self.go_to_jump(self.jump_index + 1);
+        }
+    }
+
+    fn go_to_jump(&mut self, index: usize) {
+        let jump = self.jumplist[index].clone();
+        let buf_index = match self.buffer_index(jump.file_path.as_ref()) {
+            Some(buf_index) => buf_index,
+            None => {
+                let Some(buffer) = jump.file_path.and_then(|path| self.create_new_buffer(path))
+                else {
+                    return;
+                };
+                self.buffers.push(buffer);
+                self.buffers.len() - 1
+            }
+        };
+        self.jump_index = index;
+        self.current_buf_index = buf_index;
+        let buffer = &mut self.buffers[buf_index];
+        buffer.current_position = buffer.char_idx_to_position(jump.char_idx);
+        buffer.ensure_cursor_visible();
+    }
+}
@@ -5,14 +5,33 @@ use ratatui::crossterm::execute;
 use std::fmt::Display;
 
 use super::App;
-use crate::buffer::types::Selection;
+use crate::buffer::types::{Selection, SelectionKind};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Mode {
     Normal,
     Insert,
+    Replace,
     Visual,
+    VisualLine,
+    VisualBlock,
     Command,
+    Search,
+    Confirm,
+}
+
+impl Mode {
+    pub fn is_visual(&self) -> bool {
+        matches!(self, Self::Visual | Self::VisualLine | Self::VisualBlock)
+    }
+
+    fn selection_kind(&self) -> SelectionKind {
+        match self {
+            Self::VisualLine => SelectionKind::Line,
+            Self::VisualBlock => SelectionKind::Block,
+            _ => SelectionKind::Char,
+        }
+    }
 }
 
 impl Display for Mode {
@@ -20,8 +39,13 @@ impl Display for Mode {
         match self {
             Self::Normal => write!(f, "Normal"),
             Self::Insert => write!(f, "Insert"),
+            Self::Replace => write!(f, "Replace"),
             Self::Visual => write!(f, "Visual"),
+            Self::VisualLine => write!(f, "Visual Line"),
+            Self::VisualBlock => write!(f, "Visual Block"),
             Self::Command => write!(f, "Command"),
+            Self::Search => write!(f, "Search"),
+            Self::Confirm => write!(f, "Confirm"),
         }
     }
 }
@@ -40,10 +64,17 @@ impl App {
                 self.mode = Mode::Insert;
                 execute!(terminal.backend_mut(), SetCursorStyle::BlinkingBar).unwrap_or_default();
             }
-            Mode::Visual => {
+            Mode::Replace => {
+                self.mode = Mode::Replace;
+                self.buffers[self.current_buf_index].replaced.clear();
+                execute!(terminal.backend_mut(), SetCursorStyle::BlinkingUnderScore)
+                    .unwrap_or_default();
+            }
+            Mode::Visual | Mode::VisualLine | Mode::VisualBlock => {
+                let kind = mode.selection_kind();
                 if self.mode == Mode::Normal {
                     // First time on visual, so start saving selection.
-                    self.mode = Mode::Visual;
+                    self.mode = mode;
                     self.buffers[self.current_buf_index].selection = Some(Selection {
                         start: self.buffers[self.current_buf_index]
                             .current_position
@@ -51,8 +82,16 @@ impl App {
                         end: self.buffers[self.current_buf_index]
                             .current_position
                             .clone(),
+                        kind,
                     });
                     self.buffers[self.current_buf_index].update_selected_string();
+                } else if self.mode.is_visual() && self.mode != mode {
+                    // Switching between visual modes keeps the selection, only its shape changes.
+                    self.mode = mode;
+                    if let Some(selection) = &mut self.buffers[self.current_buf_index].selection {
+                        selection.kind = kind;
+                    }
+                    self.buffers[self.current_buf_index].update_selected_string();
                 } else {
                     // If on whatever mode but normal, stop selecting and reset.
                     self.mode = Mode::Normal;
@@ -61,17 +100,27 @@ impl App {
                 }
                 execute!(terminal.backend_mut(), SetCursorStyle::BlinkingBlock).unwrap();
             }
+            Mode::Confirm => {
+                self.mode = Mode::Confirm;
+                execute!(terminal.backend_mut(), SetCursorStyle::BlinkingBlock).unwrap_or_default();
+            }
+            Mode::Search => {
+                self.mode = Mode::Search;
+                self.command = None;
+                execute!(terminal.backend_mut(), SetCursorStyle::BlinkingBlock).unwrap_or_default();
+            }
             Mode::Command => {
                 if self.mode == Mode::Normal {
                     self.mode = Mode::Command;
                     self.command = None;
                 } else {
                     // If we don't come from normal mode, just reset everything
-                    // at least for now.
+                    // at least for now. From visual mode, the command applies to the
+                    // selected lines.
+                    self.command = self.mode.is_visual().then(|| String::from("'<,'>"));
                     self.mode = Mode::Command;
                     self.buffers[self.current_buf_index].selection = None;
                     self.buffers[self.current_buf_index].update_selected_string();
-                    self.command = None;
                 }
                 execute!(terminal.backend_mut(), SetCursorStyle::BlinkingBlock).unwrap();
             }
@@ -0,0 +1,132 @@
+use ratatui::DefaultTerminal;
+
+use super::App;
+use super::modes::Mode;
+use crate::buffer::types::TextObject;
+use crate::search::Search;
+
+impl App {
+    /// Opens the search prompt, "/" searches forward and "?" backwards.
+    pub(super) fn start_search(&mut self, forward: bool, terminal: &mut DefaultTerminal) {
+        let buffer = &self.buffers[self.current_buf_index];
+        self.search_origin = Some((buffer.current_position.clone(), self.search.clone()));
+        self.search_forward = forward;
+        self.set_mode(terminal, Mode::Search);
+    }
+
+    /// Called on every change of the prompt, jumps to the first match of what has been
+    /// typed so far so that it's highlighted as you type.
+    pub(super) fn update_search(&mut self) {
+        let Some((origin, previous)) = self.search_origin.clone() else {
+            return;
+        };
+        let buffer = &mut self.buffers[self.current_buf_index];
+        buffer.current_position = origin;
+
+        // An empty pattern searches for the last one again, like in vim.
+        let pattern = self.command.as_deref().unwrap_or_default();
+        self.search = if pattern.is_empty() {
+            previous
+        } else {
+            Search::new(pattern, self.search_forward)
+        };
+        self.show_search_highlight = true;
+        if !pattern.is_empty()
+            && let Some(search) = &self.search
+            && let Some(found) =
+                buffer.find_match(&search.regex, buffer.cursor_char_idx(), search.forward)
+        {
+            buffer.current_position = buffer.char_idx_to_position(found);
+        }
+        buffer.ensure_cursor_visible();
+    }
+
+    /// Enter on the search prompt, keeps the cursor on the match.
+    pub(super) fn confirm_search(&mut self, terminal: &mut DefaultTerminal) {
+        let repeat_last = self.command.as_deref().unwrap_or_default().is_empty();
+        let origin = self.search_origin.take();
+        self.command = None;
+        self.set_mode(terminal, Mode::Normal);
+        if repeat_last && let Some(search) = &mut self.search {
+            search.forward = self.search_forward;
+            self.search_next(false, 1);
+        } else if let Some((origin, _)) = origin {
+            // The jump is from where the search started, not from the match.
+            let buffer = &mut self.buffers[self.current_buf_index];
+            let found = std::mem::replace(&mut buffer.current_position, origin);
+            self.push_jump();
+            self.buffers[self.current_buf_index].current_position = found;
+        }
+    }
+
+    /// Esc on the search prompt, goes back to where the search started.
+    pub(super) fn cancel_search(&mut self) {
+        if let Some((origin, previous)) = self.search_origin.take() {
+            self.buffers[self.current_buf_index].current_position = origin;
+            self.buffers[self.current_buf_index].ensure_cursor_visible();
+            self.search = previous;
+        }
+        self.command = None;
+    }
+
+    /// "n" (or "N" with `reverse`), jumps to the next match of the last search.
+    pub(super) fn search_next(&mut self, reverse: bool, times: usize) {
+        if self.search.is_none() {
+            return;
+        }
+        self.show_search_highlight = true;
+        self.push_jump();
+        let Some(search) = &self.search else {
+            return;
+        };
+        let buffer = &mut self.buffers[self.current_buf_index];
+        for _ in 0..times {
+            let cursor = buffer.cursor_char_idx();
+            match buffer.find_match(&search.regex, cursor, search.forward != reverse) {
+                Some(found) => buffer.current_position = buffer.char_idx_to_position(found),
+                None => break,
+            }
+        }
+        buffer.ensure_cursor_visible();
+    }
+
+    /// "*" and "#", search for the word under the cursor.
+    pub(super) fn search_word(&mut self, forward: bool, times: usize) {
+        let buffer = &mut self.buffers[self.current_buf_index];
+        let Some(range) = buffer.text_object_range(TextObject::Word { around: false }) else {
+            return;
+        };
+        let word = buffer.file_text.slice(range.clone()).to_string();
+        if !word.chars().any(|c| c.is_alphanumeric() || c == '_') {
+            return;
+        }
+        // Search from the start of the word, so that "#" skips the word itself. The jump is
+        // pushed by `search_next`, from the same line.
+        buffer.current_position = buffer.char_idx_to_position(range.start);
+        self.search = Search::word(&word, forward);
+        self.search_next(false, times);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use crate::app::App;
+    use crate::buffer::Buffer;
+    use crate::config::Config;
+
+    #[test]
+    fn test_search_word_jump() {
+        let buffer = Buffer::new(None, Rope::from_str("foo bar\n\nfoo\n"), 80, 24);
+        let mut app = App::new(vec![buffer], 80, 24, Config::default());
+        app.search_word(true, 1);
+        assert_eq!(app.buffers[0].current_position.line, 2);
+        assert_eq!(app.jumplist.len(), 1);
+
+        // No word under the cursor, no jump.
+        app.buffers[0].current_position.line = 1;
+        app.search_word(true, 1);
+        assert_eq!(app.jumplist.len(), 1);
+    }
+}
@@ -0,0 +1,240 @@
+use ratatui::DefaultTerminal;
+use regex::Regex;
+
+use super::App;
+use super::modes::Mode;
+use crate::buffer::Buffer;
+use crate::command::{Address, LineAddress, LineRange, Substitute};
+use crate::search::Search;
+
+/// A ":s///c" waiting for the user to confirm each replacement.
+#[derive(Debug, Clone)]
+pub struct ConfirmSubstitute {
+    pub regex: Regex,
+    pub replacement: String,
+    pub global: bool,
+    pub line: usize,
+    pub last_line: usize,
+    /// Char column, in `line`, of the match being confirmed.
+    pub column: usize,
+    pub match_len: usize,
+    pub changed: bool,
+    /// Set right after a replacement, an empty match can't follow it at the same column.
+    pub after_replacement: bool,
+}
+
+fn line_text(buffer: &Buffer, line: usize) -> String {
+    let text = buffer.file_text.line(line).to_string();
+    text.strip_suffix('\n').unwrap_or(&text).to_string()
+}
+
+fn byte_offset(text: &str, char_col: usize) -> usize {
+    text.char_indices()
+        .nth(char_col)
+        .map_or(text.len(), |(idx, _)| idx)
+}
+
+impl App {
+    pub(super) fn substitute(&mut self, substitute: Substitute, terminal: &mut DefaultTerminal) {
+        self.command = None;
+        self.set_mode(terminal, Mode::Normal);
+
+        let last_search = self.search.as_ref().map(|search| search.pattern.clone());
+        let regex = match substitute.regex(last_search.as_deref()) {
+            Ok(regex) => regex,
+            Err(err) => {
+                self.error = Some(err.to_string());
+                return;
+            }
+        };
+        let Some((first, last)) = self.resolve_range(substitute.range) else {
+            self.error = Some(String::from("The range uses a mark that isn't set"));
+            return;
+        };
+        // Like in vim, the pattern becomes the last search one.
+        self.search = Some(Search {
+            pattern: regex.as_str().to_string(),
+            regex: regex.clone(),
+            forward: true,
+        });
+
+        if substitute.confirm {
+            self.confirm_substitute = Some(ConfirmSubstitute {
+                regex,
+                replacement: substitute.replacement,
+                global: substitute.global,
+                line: first,
+                last_line: last,
+                column: 0,
+                match_len: 0,
+                changed: false,
+                after_replacement: false,
+            });
+            self.set_mode(terminal, Mode::Confirm);
+            self.find_substitute_match(false, terminal);
+            return;
+        }
+
+        // Bottom up, so that replacements with line breaks don't move the lines still to do.
+        let buffer = &mut self.buffers[self.current_buf_index];
+        let mut last_changed = None;
+        for line in (first..=last).rev() {
+            let text = line_text(buffer, line);
+            let replaced = if substitute.global {
+                regex.replace_all(&text, substitute.replacement.as_str())
+            } else {
+                regex.replace(&text, substitute.replacement.as_str())
+            };
+            if replaced == text {
+                continue;
+            }
+            let line_start = buffer.file_text.line_to_char(line);
+            buffer.remove_text(line_start..line_start + text.chars().count());
+            buffer.insert_text(line_start, &replaced);
+
+            let added_lines = replaced.matches('\n').count();
+            last_changed = Some(last_changed.map_or(line + added_lines, |last| last + added_lines));
+        }
+
+        if let Some(line) = last_changed {
+            buffer.current_position.line = line;
+            buffer.current_position.character = buffer.first_non_blank(line) + buffer.numbar_space;
+            buffer.ensure_cursor_visible();
+            self.notify_did_change();
+        }
+    }
+
+    /// Handles y / l / n / a / q while confirming a substitution.
+    pub(super) fn handle_confirm_key(&mut self, ch: char, terminal: &mut DefaultTerminal) {
+        match ch {
+            'y' => self.replace_substitute_match(terminal),
+            'l' => {
+                self.replace_substitute_match(terminal);
+                self.finish_substitute(terminal);
+            }
+            'n' => self.find_substitute_match(true, terminal),
+            'a' => {
+                while self.confirm_substitute.is_some() {
+                    self.replace_substitute_match(terminal);
+                }
+            }
+            'q' => self.finish_substitute(terminal),
+            _ => {}
+        }
+    }
+
+    pub(super) fn finish_substitute(&mut self, terminal: &mut DefaultTerminal) {
+        if let Some(state) = self.confirm_substitute.take()
+            && state.changed
+        {
+            self.notify_did_change();
+        }
+        self.set_mode(terminal, Mode::Normal);
+    }
+
+    fn replace_substitute_match(&mut self, terminal: &mut DefaultTerminal) {
+        let Some(state) = &mut self.confirm_substitute else {
+            return;
+        };
+        let buffer = &mut self.buffers[self.current_buf_index];
+        let text = line_text(buffer, state.line);
+        let start = byte_offset(&text, state.column);
+        let Some(captures) = state.regex.captures_at(&text, start) else {
+            self.find_substitute_match(true, terminal);
+            return;
+        };
+        let mut replaced = String::new();
+        captures.expand(&state.replacement, &mut replaced);
+
+        let char_idx = buffer.file_text.line_to_char(state.line) + state.column;
+        buffer.remove_text(char_idx..char_idx + state.match_len);
+        buffer.insert_text(char_idx, &replaced);
+        state.changed = true;
+        // Carry on after the replacement, or on the next line without "g". An empty match
+        // is stepped over so that it's not found again.
+        let added_lines = replaced.matches('\n').count();
+        state.last_line += added_lines;
+        if state.global {
+            state.line += added_lines;
+            state.column = match replaced.rfind('\n') {
+                Some(idx) => replaced[idx + 1..].chars().count(),
+                None => state.column + replaced.chars().count(),
+            } + usize::from(state.match_len == 0);
+            state.after_replacement = state.match_len > 0;
+        } else {
+            state.line += added_lines + 1;
+            state.column = 0;
+        }
+        state.match_len = 0;
+        self.find_substitute_match(false, terminal);
+    }
+
+    /// Moves the cursor to the next match to confirm, skipping the current one if `skip`.
+    fn find_substitute_match(&mut self, skip: bool, terminal: &mut DefaultTerminal) {
+        let Some(state) = &mut self.confirm_substitute else {
+            return;
+        };
+        let buffer = &mut self.buffers[self.current_buf_index];
+        if skip && state.global {
+            state.column += state.match_len.max(1);
+        } else if skip {
+            state.line += 1;
+            state.column = 0;
+        }
+
+        while state.line <= state.last_line {
+            let text = line_text(buffer, state.line);
+            if state.column <= text.chars().count()
+                && let Some(found) = state.regex.find_at(&text, byte_offset(&text, state.column))
+            {
+                let start = byte_offset(&text, state.column);
+                if std::mem::take(&mut state.after_replacement)
+                    && found.is_empty()
+                    && found.start() == start
+                {
+                    state.column += 1;
+                    continue;
+                }
+                state.column = text[..found.start()].chars().count();
+                state.match_len = found.as_str().chars().count();
+                let char_idx = buffer.file_text.line_to_char(state.line) + state.column;
+                buffer.current_position = buffer.char_idx_to_position(char_idx);
+                buffer.ensure_cursor_visible();
+                return;
+            }
+            state.line += 1;
+            state.column = 0;
+            state.after_replacement = false;
+        }
+        self.finish_substitute(terminal);
+    }
+
+    /// Resolves an Ex range to the first and last lines it covers, counted from 0.
+    fn resolve_range(&self, range: LineRange) -> Option<(usize, usize)> {
+        let buffer = &self.buffers[self.current_buf_index];
+        // The empty line after a final line break is not a line for vim.
+        let mut last_line = buffer.file_text.len_lines() - 1;
+        if last_line > 0 && buffer.file_text.line(last_line).len_chars() == 0 {
+            last_line -= 1;
+        }
+
+        let resolve = |line_address: LineAddress| -> Option<usize> {
+            let line = match line_address.address {
+                Address::Line(line) => line.saturating_sub(1),
+                Address::Current => buffer.current_position.line,
+                Address::Last => last_line,
+                Address::Mark(mark) => self.mark_line(mark)?,
+            };
+            let line = line.saturating_add_signed(line_address.offset);
+            Some(line.min(last_line))
+        };
+
+        match range {
+            LineRange::Whole => Some((0, last_line)),
+            LineRange::Lines(start, end) => {
+                let (start, end) = (resolve(start)?, resolve(end)?);
+                Some((start.min(end), start.max(end)))
+            }
+        }
+    }
+}
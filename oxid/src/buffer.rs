@@ -1,9 +1,22 @@
+mod comment;
 mod core;
+mod cursors;
 mod editing;
+mod graphemes;
+pub mod history;
+mod indent;
+mod marks;
 mod movement;
+mod navigation;
+mod pairs;
 mod rendering;
-mod visual;
+mod replace;
+mod search;
+mod textobjects;
 pub mod types;
+mod undofile;
+mod visual;
 
 pub use core::Buffer;
 pub use core::STATUSBAR_SPACE;
+pub use cursors::at_each_cursor;
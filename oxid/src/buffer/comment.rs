@@ -0,0 +1,153 @@
+use super::core::Buffer;
+
+/// How comments are written in a filetype.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentStyle<'a> {
+    /// Everything after the token is a comment, like "//".
+    Line(&'a str),
+    /// The comment goes between the two tokens, like "/* */".
+    Block(&'a str, &'a str),
+}
+
+/// Comment style of a filetype, "/* */" for the ones not in the table.
+pub fn comment_style(filetype: Option<&str>) -> CommentStyle<'static> {
+    match filetype.unwrap_or_default() {
+        "rs" | "c" | "h" | "cpp" | "hpp" | "cc" | "go" | "java" | "kt" | "js" | "jsx" | "ts"
+        | "tsx" | "swift" | "scala" | "cs" | "dart" | "zig" | "gleam" | "php" => {
+            CommentStyle::Line("//")
+        }
+        "py" | "toml" | "sh" | "bash" | "zsh" | "fish" | "rb" | "yaml" | "yml" | "pl" | "r"
+        | "nix" | "conf" | "ex" | "exs" | "jl" | "Makefile" | "Dockerfile" => {
+            CommentStyle::Line("#")
+        }
+        "lua" | "sql" | "hs" | "elm" | "ada" => CommentStyle::Line("--"),
+        "vim" => CommentStyle::Line("\""),
+        "tex" | "erl" => CommentStyle::Line("%"),
+        "lisp" | "clj" | "scm" | "el" | "asm" | "ini" => CommentStyle::Line(";"),
+        "html" | "xml" | "md" | "svg" | "vue" => CommentStyle::Block("<!--", "-->"),
+        _ => CommentStyle::Block("/*", "*/"),
+    }
+}
+
+impl Buffer {
+    /// The comment style of the buffer, the "comment" of its filetype config if set.
+    pub fn comment_style(&self) -> CommentStyle<'_> {
+        match &self.settings.comment {
+            Some(token) => CommentStyle::Line(token),
+            None => comment_style(self.filetype()),
+        }
+    }
+
+    /// "gc", comments the lines `first..=last`, or uncomments them if they are all comments
+    /// already. Blank lines are left alone, and the comment tokens go at the smallest indent
+    /// of the lines so that they line up.
+    pub fn toggle_comment(&mut self, first: usize, last: usize) {
+        let last = last.min(self.file_text.len_lines() - 1);
+        let style = match self.comment_style() {
+            CommentStyle::Line(token) => (token.to_string(), String::new()),
+            CommentStyle::Block(open, close) => (open.to_string(), close.to_string()),
+        };
+        let (open, close) = (style.0.as_str(), style.1.as_str());
+
+        let lines: Vec<(usize, String)> = (first..=last)
+            .map(|line| {
+                let text = self.file_text.line(line).to_string();
+                (line, text.trim_end_matches(['\n', '\r']).to_string())
+            })
+            .filter(|(_, text)| !text.trim().is_empty())
+            .collect();
+        if lines.is_empty() {
+            return;
+        }
+
+        let is_comment = |text: &str| {
+            let text = text.trim();
+            text.starts_with(open)
+                && text.ends_with(close)
+                && text.len() >= open.len() + close.len()
+        };
+        let uncomment = lines.iter().all(|(_, text)| is_comment(text));
+        let indent = lines
+            .iter()
+            .map(|(_, text)| text.chars().take_while(|c| c.is_whitespace()).count())
+            .min()
+            .unwrap_or_default();
+
+        // Bottom up, each line only changes itself.
+        for (line, text) in lines.into_iter().rev() {
+            let line_start = self.file_text.line_to_char(line);
+            let text_len = text.chars().count();
+            if uncomment {
+                let content_start = text.chars().take_while(|c| c.is_whitespace()).count();
+                let content_end = text_len - (text.len() - text.trim_end().len());
+                if !close.is_empty() {
+                    let close_start = content_end - close.chars().count();
+                    let before_close = text.chars().nth(close_start.saturating_sub(1));
+                    let from = close_start - usize::from(before_close == Some(' '));
+                    self.remove_text(
+                        line_start + from.max(content_start)..line_start + content_end,
+                    );
+                }
+                let open_end = content_start + open.chars().count();
+                let after_open = text.chars().nth(open_end);
+                let to = open_end + usize::from(after_open == Some(' '));
+                self.remove_text(line_start + content_start..line_start + to.min(text_len));
+            } else {
+                if !close.is_empty() {
+                    self.insert_text(line_start + text_len, &format!(" {close}"));
+                }
+                self.insert_text(line_start + indent, &format!("{open} "));
+            }
+        }
+
+        self.current_position.line = first;
+        self.current_position.character = self.first_non_blank(first) + self.numbar_space;
+        self.ensure_cursor_visible();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::*;
+
+    fn buffer(file_path: &str, text: &str) -> Buffer {
+        Buffer::new(Some(file_path.to_string()), Rope::from_str(text), 80, 24)
+    }
+
+    #[test]
+    fn test_toggle_comment() {
+        let mut rust = buffer(
+            "main.rs",
+            "fn main() {\n    let a = 1;\n\n    // let b = 2;\n}\n",
+        );
+        rust.toggle_comment(1, 3);
+        assert_eq!(
+            rust.file_text.to_string(),
+            "fn main() {\n    // let a = 1;\n\n    // // let b = 2;\n}\n"
+        );
+        rust.toggle_comment(1, 1);
+        assert_eq!(
+            rust.file_text.to_string(),
+            "fn main() {\n    let a = 1;\n\n    // // let b = 2;\n}\n"
+        );
+
+        let mut python = buffer("main.py", "if a:\n    b()\n");
+        python.toggle_comment(0, 1);
+        assert_eq!(python.file_text.to_string(), "# if a:\n#     b()\n");
+        python.toggle_comment(0, 1);
+        assert_eq!(python.file_text.to_string(), "if a:\n    b()\n");
+
+        let mut css = buffer("style.css", "a {}\n");
+        css.toggle_comment(0, 0);
+        assert_eq!(css.file_text.to_string(), "/* a {} */\n");
+        css.toggle_comment(0, 0);
+        assert_eq!(css.file_text.to_string(), "a {}\n");
+
+        // "gcc" in a file without extension.
+        let mut makefile = buffer("/src/Makefile", "all:\n\tcargo build\n");
+        makefile.toggle_comment(0, 0);
+        assert_eq!(makefile.file_text.to_string(), "# all:\n\tcargo build\n");
+    }
+}
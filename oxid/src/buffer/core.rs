@@ -1,20 +1,51 @@
+use std::collections::HashMap;
+use std::path::Path;
+
 use ropey::Rope;
 
-use super::types::{BufferPosition, Selection};
+use super::history::History;
+use super::types::{BufferPosition, CharSearch, Selection};
+use crate::config::FiletypeConfig;
+use crate::encoding::FileFormat;
 
 pub const STATUSBAR_SPACE: usize = 1;
 
 pub struct Buffer {
     pub file_path: Option<String>,
     pub file_text: Rope,
+    /// Encoding, BOM and line breaks of the file, kept when saving it.
+    pub file_format: FileFormat,
     pub viewport_width: usize,
     pub viewport_height: usize,
     pub vertical_scroll: usize,
     pub horizontal_scroll: usize,
     pub current_position: BufferPosition,
+    /// Extra cursors, edits made in Insert mode also happen at each of them.
+    pub cursors: Vec<BufferPosition>,
     pub numbar_space: usize,
     pub selection: Option<Selection>,
     pub selected_string: Option<String>,
+    /// The last visual selection, what the "'<" and "'>" marks refer to.
+    pub last_selection: Option<Selection>,
+    /// Char index of each mark, "a"-"z" and the "A"-"Z" ones set in this buffer.
+    pub marks: HashMap<char, usize>,
+    /// Last "f", "t", "F" or "T", repeated by ";" and ",".
+    pub last_char_search: Option<CharSearch>,
+    /// Graphemes overwritten in Replace mode (`None` past the end of the line), so that
+    /// Backspace can bring them back.
+    pub replaced: Vec<Option<String>>,
+    pub history: History,
+    /// Hash of the text as last loaded from or saved to disk. The undo file is only written
+    /// while the buffer matches it, as it's only restored on that text.
+    pub saved_hash: Option<String>,
+    /// Indent settings of the filetype, from the config.
+    pub settings: FiletypeConfig,
+    /// Opened in large-file mode, without LSP.
+    pub large_file: bool,
+    /// Chunks of a large file are still being loaded, it can't be saved until they all are.
+    pub loading: bool,
+    /// Bytes of the file couldn't be decoded and were replaced, it can't be saved.
+    pub lossy: bool,
 }
 
 impl Buffer {
@@ -28,6 +59,7 @@ impl Buffer {
         Buffer {
             file_path,
             file_text,
+            file_format: FileFormat::default(),
             viewport_width: viewport_width - numbar_space,
             viewport_height: viewport_height - STATUSBAR_SPACE,
             vertical_scroll: 0,
@@ -36,9 +68,74 @@ impl Buffer {
                 line: 0,
                 character: numbar_space,
             },
+            cursors: Vec::new(),
             numbar_space,
             selection: None,
             selected_string: None,
+            last_selection: None,
+            marks: HashMap::new(),
+            last_char_search: None,
+            replaced: Vec::new(),
+            history: History::new(),
+            saved_hash: None,
+            settings: FiletypeConfig::default(),
+            large_file: false,
+            loading: false,
+            lossy: false,
         }
     }
+
+    /// An empty buffer for a large file, filled by the chunks loaded in the background.
+    pub fn new_large_file(
+        file_path: String,
+        viewport_width: usize,
+        viewport_height: usize,
+    ) -> Self {
+        let mut buffer = Self::new(
+            Some(file_path),
+            Rope::new(),
+            viewport_width,
+            viewport_height,
+        );
+        buffer.large_file = true;
+        buffer.loading = true;
+        buffer
+    }
+
+    /// Char index into the rope of the given position, clamped to the end of its line.
+    pub fn position_to_char_idx(&self, position: &BufferPosition) -> usize {
+        let line = position.line.min(self.file_text.len_lines() - 1);
+        let line_len = self.file_text.line(line).len_chars();
+        let character = position.character.saturating_sub(self.numbar_space);
+        self.file_text.line_to_char(line) + character.min(line_len)
+    }
+
+    pub fn char_idx_to_position(&self, char_idx: usize) -> BufferPosition {
+        let char_idx = char_idx.min(self.file_text.len_chars());
+        let line = self.file_text.char_to_line(char_idx);
+        BufferPosition {
+            line,
+            character: char_idx - self.file_text.line_to_char(line) + self.numbar_space,
+        }
+    }
+
+    pub fn cursor_char_idx(&self) -> usize {
+        self.position_to_char_idx(&self.current_position)
+    }
+
+    /// The filetype is the file extension, or the file name for files without one (e.g.
+    /// "Makefile"), what `[[lsp]]` and `[[filetype]]` in the config refer to.
+    pub fn filetype(&self) -> Option<&str> {
+        let path = Path::new(self.file_path.as_deref()?);
+        path.extension().or(path.file_name())?.to_str()
+    }
+
+    /// Length of a line without its line break.
+    pub fn line_content_len(&self, line: usize) -> usize {
+        self.file_text
+            .line(line)
+            .chars()
+            .take_while(|c| *c != '\n')
+            .count()
+    }
 }
@@ -0,0 +1,182 @@
+use regex::Regex;
+
+use super::core::Buffer;
+use super::types::{BufferPosition, SelectionKind};
+
+/// Runs `action` at the main cursor and at every extra one of the buffer returned by
+/// `buffer`. Cursors are visited bottom up, so an action only moves the cursors already done,
+/// which are shifted along. `target` is the buffer itself, or the app for actions that need
+/// more than the buffer (e.g. operators writing to registers).
+pub fn at_each_cursor<T>(
+    target: &mut T,
+    buffer: fn(&mut T) -> &mut Buffer,
+    mut action: impl FnMut(&mut T),
+) {
+    if buffer(target).cursors.is_empty() {
+        action(target);
+        return;
+    }
+
+    let current = buffer(target);
+    let mut cursors: Vec<(BufferPosition, bool)> = current
+        .cursors
+        .drain(..)
+        .map(|position| (position, false))
+        .chain([(current.current_position.clone(), true)])
+        .collect();
+    cursors.sort_by_key(|(position, _)| std::cmp::Reverse((position.line, position.character)));
+
+    let mut done: Vec<(BufferPosition, bool)> = Vec::new();
+    for (position, main) in cursors {
+        let current = buffer(target);
+        let len_chars = current.file_text.len_chars();
+        let numbar_space = current.numbar_space;
+        let done_idx: Vec<usize> = done
+            .iter()
+            .map(|(position, _)| current.position_to_char_idx(position))
+            .collect();
+
+        current.current_position = position;
+        action(target);
+
+        let current = buffer(target);
+        let delta = current.file_text.len_chars() as isize - len_chars as isize;
+        if delta != 0 || numbar_space != current.numbar_space {
+            for ((position, _), char_idx) in done.iter_mut().zip(done_idx) {
+                *position = current.char_idx_to_position(char_idx.saturating_add_signed(delta));
+            }
+        }
+        done.push((current.current_position.clone(), main));
+    }
+
+    // Cursors that met (e.g. backspacing over the same line break) become one.
+    let current = buffer(target);
+    let main = done.iter().position(|(_, main)| *main).unwrap_or(0);
+    current.current_position = done.swap_remove(main).0;
+    for (position, _) in done {
+        if position != current.current_position && !current.cursors.contains(&position) {
+            current.cursors.push(position);
+        }
+    }
+    current.ensure_cursor_visible();
+}
+
+impl Buffer {
+    /// Runs `action` at every cursor, see [`at_each_cursor`].
+    pub fn at_each_cursor(&mut self, action: impl FnMut(&mut Buffer)) {
+        at_each_cursor(self, |buffer| buffer, action);
+    }
+
+    /// Adds a cursor on the line below the lowest cursor (or above the highest one), at the
+    /// column of the main cursor.
+    pub fn add_cursor_vertical(&mut self, below: bool) {
+        let lines = self
+            .cursors
+            .iter()
+            .chain([&self.current_position])
+            .map(|position| position.line);
+        let line = if below {
+            lines.max().unwrap_or_default() + 1
+        } else {
+            match lines.min().unwrap_or_default().checked_sub(1) {
+                Some(line) => line,
+                None => return,
+            }
+        };
+        // The empty line after a final line break is not a line to edit.
+        let last_line = self.file_text.len_lines() - 1;
+        if line > last_line || (line == last_line && line > 0 && self.line_content_len(line) == 0) {
+            return;
+        }
+
+        let column = self.current_position.character - self.numbar_space;
+        self.cursors.push(BufferPosition {
+            line,
+            character: column.min(self.line_content_len(line)) + self.numbar_space,
+        });
+    }
+
+    /// Adds a cursor at the next match of `regex` that has none yet. The main cursor moves
+    /// there and the old one stays as an extra cursor.
+    pub fn add_cursor_at_next_match(&mut self, regex: &Regex) {
+        let mut char_idx = self.cursor_char_idx();
+        let start = char_idx;
+        loop {
+            let Some(found) = self.find_match(regex, char_idx, true) else {
+                return;
+            };
+            let position = self.char_idx_to_position(found);
+            if position != self.current_position && !self.cursors.contains(&position) {
+                let previous = std::mem::replace(&mut self.current_position, position);
+                self.cursors.push(previous);
+                self.ensure_cursor_visible();
+                return;
+            }
+            // Every match has a cursor already once the search wraps back to the start.
+            if found == start {
+                return;
+            }
+            char_idx = found;
+        }
+    }
+
+    /// Turns the visual selection into one cursor per line: at the left column of a block,
+    /// at the end of each line otherwise.
+    pub fn split_selection(&mut self) {
+        let Some(selection) = self.selection.take() else {
+            return;
+        };
+        self.selected_string = None;
+        let (first, last, _, _) = selection.bounds(self.numbar_space);
+        let main_line = self.current_position.line;
+        for line in first..=last {
+            let line_len = self.line_content_len(line);
+            let column = match selection.kind {
+                SelectionKind::Block => self.block_columns(&selection, line).start,
+                SelectionKind::Char | SelectionKind::Line => line_len,
+            };
+            let position = BufferPosition {
+                line,
+                character: column + self.numbar_space,
+            };
+            if line == main_line {
+                self.current_position = position;
+            } else {
+                self.cursors.push(position);
+            }
+        }
+        self.ensure_cursor_visible();
+    }
+
+    pub fn clear_cursors(&mut self) {
+        self.cursors.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::*;
+
+    #[test]
+    fn test_insert_at_each_cursor() {
+        let mut buffer = Buffer::new(None, Rope::from_str("ab\ncd\nef\n"), 80, 24);
+        buffer.current_position.character += 1;
+        buffer.add_cursor_vertical(true);
+        buffer.add_cursor_vertical(true);
+        assert_eq!(buffer.cursors.len(), 2);
+
+        buffer.at_each_cursor(|buffer| buffer.insert_char('x'));
+        assert_eq!(buffer.file_text.to_string(), "axb\ncxd\nexf\n");
+        buffer.at_each_cursor(|buffer| buffer.enter_key());
+        assert_eq!(buffer.file_text.to_string(), "ax\nb\ncx\nd\nex\nf\n");
+        buffer.at_each_cursor(|buffer| buffer.remove_char());
+        assert_eq!(buffer.file_text.to_string(), "axb\ncxd\nexf\n");
+
+        // A single undo step for everything typed at every cursor.
+        buffer.commit_history();
+        buffer.undo();
+        assert_eq!(buffer.file_text.to_string(), "ab\ncd\nef\n");
+    }
+}
@@ -1,70 +1,102 @@
-use super::core::Buffer;
-
-impl Buffer {
-    pub fn paste(&mut self, paste_string: String) {
-        let curr_line = self.current_position.line;
-        let curr_char = self.current_position.character - self.numbar_space;
-
-        let curr_string = self.file_text.line(curr_line).to_string();
+use std::ops::Range;
 
-        // Ensure curr_char doesn't exceed the line length
-        let safe_curr_char = curr_char.min(curr_string.len());
+use ropey::Rope;
 
-        let start_until_cursor = &curr_string[0..safe_curr_char];
-        let rest_original_string = &curr_string[safe_curr_char..];
-
-        let newlines: Vec<_> = paste_string.lines().collect();
+use super::core::Buffer;
+use super::history::Edit;
+use super::types::BufferPosition;
 
-        // Handle empty paste string
-        if newlines.is_empty() {
+impl Buffer {
+    /// Pastes `text` after the cursor ("p") or before it ("P"). Linewise text goes below
+    /// or above the current line instead.
+    pub fn paste(&mut self, text: &str, linewise: bool, before: bool) {
+        if text.is_empty() {
             return;
         }
 
-        if newlines.len() == 1 {
-            // Single line paste means just insert at cursor position.
-            let mut new_str = String::from(start_until_cursor);
-            new_str.push_str(&paste_string);
-            new_str.push_str(rest_original_string);
-
-            let line_len = self.file_text.line(curr_line).len_chars();
-            let start_line_char = self.file_text.line_to_char(curr_line);
-            let end_line_char = start_line_char + line_len;
-
-            if start_line_char <= end_line_char && end_line_char <= self.file_text.len_chars() {
-                self.file_text.remove(start_line_char..end_line_char);
-                self.file_text.insert(start_line_char, &new_str);
+        if linewise {
+            let line = self.current_position.line + usize::from(!before);
+            let mut text = text.to_string();
+            if !text.ends_with('\n') {
+                text.push('\n');
             }
+            let char_idx = if line < self.file_text.len_lines() {
+                self.file_text.line_to_char(line)
+            } else {
+                // Pasting below a last line that has no line break.
+                text.pop();
+                text.insert(0, '\n');
+                self.file_text.len_chars()
+            };
+            self.insert_text(char_idx, &text);
+            self.current_position.line = line;
+            self.current_position.character = self.first_non_blank(line) + self.numbar_space;
         } else {
-            // Multi-line paste
-            let mut new_text = String::from(start_until_cursor);
-            new_text.push_str(&paste_string);
-            new_text.push_str(rest_original_string);
-
-            let line_len = self.file_text.line(curr_line).len_chars();
-            let start_line_char = self.file_text.line_to_char(curr_line);
-            let end_line_char = start_line_char + line_len;
-
-            self.file_text.remove(start_line_char..end_line_char);
-            self.file_text.insert(start_line_char, &new_text);
+            let mut char_idx = self.cursor_char_idx();
+            if !before && self.line_content_len(self.current_position.line) > 0 {
+                char_idx += 1;
+            }
+            self.insert_text(char_idx, text);
+            let end = char_idx + text.chars().count() - 1;
+            self.current_position = self.char_idx_to_position(end);
         }
-        self.update_numbar_space();
         self.ensure_cursor_visible();
     }
 
     pub fn update_numbar_space(&mut self) {
         let numbar_space = self.file_text.len_lines().to_string().len() + 1;
         if self.numbar_space != numbar_space {
-            self.numbar_space = self.file_text.len_lines().to_string().len() + 1;
+            // Cursor columns are stored including the number bar, so shift them along.
+            for position in self.cursors.iter_mut().chain([&mut self.current_position]) {
+                position.character = position.character + numbar_space - self.numbar_space;
+            }
+            self.numbar_space = numbar_space;
         }
     }
 
+    /// Inserts `text` at `char_idx` and records it in the undo history.
+    pub fn insert_text(&mut self, char_idx: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let cursor = self.cursor_char_idx();
+        self.file_text.insert(char_idx, text);
+        let edit = Edit {
+            char_idx,
+            removed: String::new(),
+            inserted: text.to_string(),
+        };
+        self.shift_marks(&edit);
+        self.history.record(edit, cursor);
+        self.update_numbar_space();
+    }
+
+    /// Removes the chars in `range`, records it in the undo history and returns them.
+    pub fn remove_text(&mut self, range: Range<usize>) -> String {
+        if range.is_empty() {
+            return String::new();
+        }
+        let cursor = self.cursor_char_idx();
+        let removed = self.file_text.slice(range.clone()).to_string();
+        self.file_text.remove(range.clone());
+        let edit = Edit {
+            char_idx: range.start,
+            removed: removed.clone(),
+            inserted: String::new(),
+        };
+        self.shift_marks(&edit);
+        self.history.record(edit, cursor);
+        self.update_numbar_space();
+        removed
+    }
+
     pub fn insert_char(&mut self, ch: char) {
+        self.dedent_closing_bracket(ch);
         let line = self.current_position.line;
         let character = self.current_position.character - self.numbar_space;
         let mut char_idx = self.file_text.line_to_char(line);
         char_idx = char_idx.saturating_add(character);
-        self.file_text.insert_char(char_idx, ch);
-        self.update_numbar_space();
+        self.insert_text(char_idx, &ch.to_string());
         self.current_position.character = self.current_position.character.saturating_add(1);
 
         self.ensure_cursor_visible();
@@ -84,19 +116,19 @@ impl Buffer {
         }
 
         if curr_char > 0 {
-            // Regular backspace, just delete the character before the cursor
-            self.file_text
-                .remove(line_start_char + curr_char - 1..line_start_char + curr_char);
-            self.current_position.character = self.current_position.character.saturating_sub(1);
+            // Regular backspace, just delete the grapheme before the cursor
+            let start = self.previous_grapheme(curr_line, curr_char);
+            self.remove_text(line_start_char + start..line_start_char + curr_char);
+            self.current_position.character = start + self.numbar_space;
         } else {
             // We're at the start of a line, merge with previous line
             let prev_line = curr_line - 1;
-            let prev_line_len = self.file_text.line(prev_line).len_chars() -1;
+            let prev_line_len = self.file_text.line(prev_line).len_chars() - 1;
 
             // Remove the line break
             let prev_line_end = self.file_text.line_to_char(prev_line) + prev_line_len;
             let curr_line_start = line_start_char;
-            self.file_text.remove(prev_line_end..curr_line_start);
+            self.remove_text(prev_line_end..curr_line_start);
 
             // Update cursor and move to end of previous line
             self.current_position.line = prev_line;
@@ -106,25 +138,237 @@ impl Buffer {
         self.ensure_cursor_visible();
     }
 
+    /// Splits the line at the cursor, the new line is indented like the current one (see
+    /// `indent_after`).
     pub fn enter_key(&mut self) {
         let line = self.current_position.line;
         let character = self.current_position.character - self.numbar_space;
         let mut char_idx = self.file_text.line_to_char(line);
         char_idx = char_idx.saturating_add(character);
-        self.file_text.insert(char_idx, "\n");
-        self.update_numbar_space();
+        let before_cursor = self
+            .file_text
+            .slice(char_idx - character..char_idx)
+            .to_string();
+        let indent = self.indent_after(line, &before_cursor);
+        self.insert_text(char_idx, &format!("\n{indent}"));
         self.current_position.line = self.current_position.line.saturating_add(1);
+        self.current_position.character = indent.chars().count() + self.numbar_space;
+        self.ensure_cursor_visible();
+    }
+
+    /// Char range of the lines `first..=last`, including their line breaks.
+    pub fn lines_char_range(&self, first: usize, last: usize) -> Range<usize> {
+        let last = last.min(self.file_text.len_lines() - 1);
+        let start = self.file_text.line_to_char(first);
+        let end = self.file_text.line_to_char(last) + self.file_text.line(last).len_chars();
+        start..end
+    }
+
+    /// Text of the lines `first..=last`, always ending with a line break.
+    pub fn lines_text(&self, first: usize, last: usize) -> String {
+        let mut text = self
+            .file_text
+            .slice(self.lines_char_range(first, last))
+            .to_string();
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Deletes the lines `first..=last` and returns their text.
+    pub fn delete_lines(&mut self, first: usize, last: usize) -> String {
+        let text = self.lines_text(first, last);
+        let mut range = self.lines_char_range(first, last);
+        // The last line has no line break of its own, take the previous one instead.
+        if range.end == self.file_text.len_chars()
+            && !self
+                .file_text
+                .slice(range.clone())
+                .to_string()
+                .ends_with('\n')
+            && range.start > 0
+        {
+            range.start -= 1;
+        }
+        self.remove_text(range);
+
+        self.current_position.line = first.min(self.file_text.len_lines() - 1);
+        self.current_position.character =
+            self.first_non_blank(self.current_position.line) + self.numbar_space;
+        self.ensure_cursor_visible();
+        text
+    }
+
+    /// Empties the lines `first..=last` leaving a single blank line, returns the removed text.
+    pub fn clear_lines(&mut self, first: usize, last: usize) -> String {
+        let text = self.lines_text(first, last);
+        let range = self.lines_char_range(first, last);
+        let content_end = if self
+            .file_text
+            .slice(range.clone())
+            .to_string()
+            .ends_with('\n')
+        {
+            range.end - 1
+        } else {
+            range.end
+        };
+        self.remove_text(range.start..content_end);
+
+        self.current_position.line = first;
         self.current_position.character = self.numbar_space;
         self.ensure_cursor_visible();
+        text
+    }
+
+    pub fn indent_lines(&mut self, first: usize, last: usize) {
+        let last = last.min(self.file_text.len_lines() - 1);
+        for line in first..=last {
+            if self.file_text.line(line).chars().all(|c| c == '\n') {
+                continue;
+            }
+            let line_start = self.file_text.line_to_char(line);
+            self.insert_text(line_start, &self.indent_unit());
+        }
+        self.current_position.line = first;
+        self.current_position.character = self.first_non_blank(first) + self.numbar_space;
+        self.ensure_cursor_visible();
     }
 
-    pub fn save_file(&self) -> anyhow::Result<()> {
-        let text = self.file_text.to_string();
+    pub fn dedent_lines(&mut self, first: usize, last: usize) {
+        let last = last.min(self.file_text.len_lines() - 1);
+        for line in first..=last {
+            let line_start = self.file_text.line_to_char(line);
+            let removable = match self.file_text.line(line).chars().next() {
+                Some('\t') => 1,
+                _ => self
+                    .file_text
+                    .line(line)
+                    .chars()
+                    .take(self.settings.shiftwidth)
+                    .take_while(|c| *c == ' ')
+                    .count(),
+            };
+            self.remove_text(line_start..line_start + removable);
+        }
+        self.current_position.line = first;
+        self.current_position.character = self.first_non_blank(first) + self.numbar_space;
+        self.ensure_cursor_visible();
+    }
+
+    /// Joins the lines `first..=last` into one ("J"). With `normalize`, the indent of each
+    /// joined line becomes a single space, or nothing before a ")" or after a blank. Without
+    /// it ("gJ"), only the line breaks go.
+    pub fn join_lines(&mut self, first: usize, last: usize, normalize: bool) {
+        let last = last.min(self.last_line());
+        let line_start = self.file_text.line_to_char(first);
+        let mut column = self.current_position.character - self.numbar_space;
+        for _ in first..last {
+            let line_len = self.line_content_len(first);
+            let join_idx = line_start + line_len;
+            let next_start = self.file_text.line_to_char(first + 1);
+            let indent = if normalize {
+                self.first_non_blank(first + 1)
+            } else {
+                0
+            };
+            self.remove_text(join_idx..next_start + indent);
+
+            let ends_blank = self
+                .file_text
+                .slice(line_start..join_idx)
+                .chars()
+                .last()
+                .is_none_or(|c| c.is_whitespace());
+            let next = self.file_text.get_char(join_idx).filter(|c| *c != '\n');
+            if normalize && !ends_blank && next.is_some_and(|c| c != ')') {
+                self.insert_text(join_idx, " ");
+            }
+            column = line_len;
+        }
+        self.current_position = BufferPosition {
+            line: first,
+            character: column + self.numbar_space,
+        };
+        self.ensure_cursor_visible();
+    }
+
+    /// Appends a chunk of a large file being loaded. It's not an edit, so it can't be undone.
+    pub fn append_loaded(&mut self, text: &str, done: bool) {
+        self.file_text.append(Rope::from_str(text));
+        self.update_numbar_space();
+        self.loading = !done;
+    }
+
+    pub fn save_file(&mut self) -> anyhow::Result<()> {
+        if self.loading {
+            anyhow::bail!("The file is still loading");
+        }
+        if self.lossy {
+            anyhow::bail!("The file has bytes that couldn't be decoded, saving it would lose them");
+        }
+        let bytes = self.file_format.encode(&self.file_text.to_string())?;
         if let Some(filepath) = &self.file_path {
-            std::fs::write(filepath, text)?;
+            std::fs::write(filepath, bytes)?;
+            self.mark_saved();
+            // Losing the undo history is not worth failing the save for.
+            _ = self.save_undo_file();
         } else {
             anyhow::bail!("No filepath provided, cannot save file...")
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::*;
+    use crate::encoding::FileEncoding;
+
+    #[test]
+    fn test_join_lines() {
+        let mut buffer = Buffer::new(
+            None,
+            Rope::from_str("foo(a,\n    b\n)\n\n  x \n    y\n"),
+            80,
+            24,
+        );
+        buffer.join_lines(0, 2, true);
+        assert_eq!(buffer.file_text.to_string(), "foo(a, b)\n\n  x \n    y\n");
+        assert_eq!(buffer.cursor_char_idx(), 8);
+        buffer.join_lines(1, 2, true);
+        assert_eq!(buffer.file_text.to_string(), "foo(a, b)\nx \n    y\n");
+        buffer.join_lines(1, 2, false);
+        assert_eq!(buffer.file_text.to_string(), "foo(a, b)\nx     y\n");
+    }
+
+    #[test]
+    fn test_save_unencodable_char() {
+        let path = std::env::temp_dir().join("oxid_test_save_latin1");
+        let mut buffer = Buffer::new(
+            Some(path.to_string_lossy().into_owned()),
+            Rope::from_str("café 日\n"),
+            80,
+            24,
+        );
+        buffer.file_format.encoding = FileEncoding::Latin1;
+        assert!(buffer.save_file().is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_append_loaded_widens_number_bar() {
+        let mut buffer = Buffer::new(None, Rope::new(), 80, 24);
+        buffer.append_loaded(&"line\n".repeat(8), false);
+        buffer.add_cursor_vertical(true);
+        let cursor = buffer.cursors[0].clone();
+        buffer.append_loaded(&"line\n".repeat(100), true);
+        assert_eq!(buffer.numbar_space, 4);
+        assert_eq!(buffer.current_position.character, buffer.numbar_space);
+        assert_eq!(buffer.cursors[0].character, cursor.character + 2);
+        assert!(!buffer.loading);
+    }
+}
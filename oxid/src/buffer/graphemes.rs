@@ -0,0 +1,103 @@
+use oxid_lsp::types::Position;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use super::core::Buffer;
+use super::types::BufferPosition;
+
+impl Buffer {
+    /// Grapheme clusters of a line with the column of their first char. An accented letter
+    /// written with a combining mark, or an emoji made of several chars, is one grapheme:
+    /// the cursor moves over it and the screen shows it as a whole.
+    pub fn line_graphemes(&self, line: usize) -> Vec<(usize, String)> {
+        let text = self.file_text.line(line).to_string();
+        let mut column = 0;
+        text.graphemes(true)
+            .map(|grapheme| {
+                let start = column;
+                column += grapheme.chars().count();
+                (start, grapheme.to_string())
+            })
+            .collect()
+    }
+
+    /// Cells taken on screen by a grapheme starting at `display_col`. Tabs go up to the next
+    /// tab stop, wide chars (CJK, most emoji) take two cells, and a line break or a lone
+    /// zero-width char takes one, so that the cursor can be drawn on it.
+    pub fn grapheme_width(&self, grapheme: &str, display_col: usize) -> usize {
+        if grapheme == "\t" {
+            let tabstop = self.settings.tabstop.max(1);
+            return tabstop - display_col % tabstop;
+        }
+        if grapheme.starts_with(['\n', '\r']) {
+            return 1;
+        }
+        grapheme.width().max(1)
+    }
+
+    /// Column of the first char of the grapheme `column` is part of.
+    pub fn grapheme_start(&self, line: usize, column: usize) -> usize {
+        self.line_graphemes(line)
+            .into_iter()
+            .find(|(start, grapheme)| column < start + grapheme.chars().count())
+            .map_or(column, |(start, _)| start)
+    }
+
+    /// Column of the grapheme after the one at `column`.
+    pub fn next_grapheme(&self, line: usize, column: usize) -> usize {
+        self.line_graphemes(line)
+            .into_iter()
+            .find(|(start, _)| *start > column)
+            .map_or(column + 1, |(start, _)| start)
+    }
+
+    /// Column of the grapheme before the one at `column`.
+    pub fn previous_grapheme(&self, line: usize, column: usize) -> usize {
+        self.line_graphemes(line)
+            .into_iter()
+            .map(|(start, _)| start)
+            .take_while(|start| *start < column)
+            .last()
+            .unwrap_or(0)
+    }
+
+    /// Position sent to the LSP server, which counts columns in UTF-16 code units.
+    pub fn to_lsp_position(&self, position: &BufferPosition) -> Position {
+        let column = position.character.saturating_sub(self.numbar_space);
+        let character = self
+            .file_text
+            .line(position.line)
+            .chars()
+            .take(column)
+            .map(char::len_utf16)
+            .sum();
+        Position {
+            line: position.line,
+            character,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::*;
+
+    #[test]
+    fn test_graphemes_and_lsp_positions() {
+        // "e" and a combining acute accent, a CJK char and an emoji outside the BMP.
+        let buffer = Buffer::new(None, Rope::from_str("ce\u{301}日🙂x\n"), 80, 24);
+        assert_eq!(buffer.next_grapheme(0, 1), 3);
+        assert_eq!(buffer.previous_grapheme(0, 3), 1);
+        assert_eq!(buffer.grapheme_start(0, 2), 1);
+        assert_eq!(buffer.grapheme_width("日", 0), 2);
+        assert_eq!(buffer.grapheme_width("e\u{301}", 0), 1);
+
+        let position = BufferPosition {
+            line: 0,
+            character: 5 + buffer.numbar_space,
+        };
+        assert_eq!(buffer.to_lsp_position(&position).character, 6);
+    }
+}
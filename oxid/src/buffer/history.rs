@@ -0,0 +1,260 @@
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+
+use super::core::Buffer;
+
+/// A single change to the rope. Indices are char indices into the rope at the
+/// moment the edit was applied.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Edit {
+    pub char_idx: usize,
+    pub removed: String,
+    pub inserted: String,
+}
+
+impl Edit {
+    /// The edit that undoes this one.
+    pub fn reversed(&self) -> Edit {
+        Edit {
+            char_idx: self.char_idx,
+            removed: self.inserted.clone(),
+            inserted: self.removed.clone(),
+        }
+    }
+
+    fn apply(&self, rope: &mut Rope) {
+        let removed_len = self.removed.chars().count();
+        rope.remove(self.char_idx..self.char_idx + removed_len);
+        rope.insert(self.char_idx, &self.inserted);
+    }
+
+    fn revert(&self, rope: &mut Rope) {
+        let inserted_len = self.inserted.chars().count();
+        rope.remove(self.char_idx..self.char_idx + inserted_len);
+        rope.insert(self.char_idx, &self.removed);
+    }
+}
+
+/// A node of the undo tree. Cursors are stored as char indices so they stay valid
+/// no matter how wide the number bar was when the change was made.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Revision {
+    pub parent: usize,
+    pub last_child: Option<usize>,
+    pub edits: Vec<Edit>,
+    pub cursor_before: usize,
+    pub cursor_after: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct History {
+    pub revisions: Vec<Revision>,
+    pub current: usize,
+    #[serde(skip)]
+    pending: Vec<Edit>,
+    #[serde(skip)]
+    pending_cursor: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl History {
+    pub fn new() -> Self {
+        History {
+            // Root revision, it holds no edits and is its own parent.
+            revisions: vec![Revision {
+                parent: 0,
+                last_child: None,
+                edits: Vec::new(),
+                cursor_before: 0,
+                cursor_after: 0,
+            }],
+            current: 0,
+            pending: Vec::new(),
+            pending_cursor: 0,
+        }
+    }
+
+    /// Records an edit that has already been applied to the rope. Consecutive
+    /// inserts are merged so that a whole insert session is stored compactly.
+    pub fn record(&mut self, edit: Edit, cursor: usize) {
+        if self.pending.is_empty() {
+            self.pending_cursor = cursor;
+        }
+
+        if let Some(last) = self.pending.last_mut()
+            && last.removed.is_empty()
+            && edit.removed.is_empty()
+            && edit.char_idx == last.char_idx + last.inserted.chars().count()
+        {
+            last.inserted.push_str(&edit.inserted);
+            return;
+        }
+        self.pending.push(edit);
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Closes the pending edits into a new revision, child of the current one.
+    pub fn commit(&mut self, cursor_after: usize) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        let revision = Revision {
+            parent: self.current,
+            last_child: None,
+            edits: std::mem::take(&mut self.pending),
+            cursor_before: self.pending_cursor,
+            cursor_after,
+        };
+        self.revisions.push(revision);
+        let new_idx = self.revisions.len() - 1;
+        self.revisions[self.current].last_child = Some(new_idx);
+        self.current = new_idx;
+    }
+
+    /// Reverts the current revision and returns where the cursor should go.
+    pub fn undo(&mut self, rope: &mut Rope) -> Option<usize> {
+        if self.current == 0 {
+            return None;
+        }
+
+        let revision = &self.revisions[self.current];
+        for edit in revision.edits.iter().rev() {
+            edit.revert(rope);
+        }
+        let cursor = revision.cursor_before;
+        let parent = revision.parent;
+        // Remember which branch we came from so redo follows it back.
+        self.revisions[parent].last_child = Some(self.current);
+        self.current = parent;
+        Some(cursor)
+    }
+
+    /// Re-applies the most recent child revision and returns where the cursor should go.
+    pub fn redo(&mut self, rope: &mut Rope) -> Option<usize> {
+        let child = self.revisions[self.current].last_child?;
+
+        let revision = &self.revisions[child];
+        for edit in revision.edits.iter() {
+            edit.apply(rope);
+        }
+        self.current = child;
+        Some(revision.cursor_after)
+    }
+}
+
+impl Buffer {
+    pub fn commit_history(&mut self) {
+        if self.history.has_pending() {
+            let cursor = self.cursor_char_idx();
+            self.history.commit(cursor);
+        }
+    }
+
+    /// Returns true if there was something to undo.
+    pub fn undo(&mut self) -> bool {
+        self.commit_history();
+        let edits = self.history.revisions[self.history.current].edits.clone();
+        for edit in edits.iter().rev() {
+            self.shift_marks(&edit.reversed());
+        }
+        match self.history.undo(&mut self.file_text) {
+            Some(cursor) => {
+                self.restore_cursor(cursor);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns true if there was something to redo.
+    pub fn redo(&mut self) -> bool {
+        self.commit_history();
+        if let Some(child) = self.history.revisions[self.history.current].last_child {
+            let edits = self.history.revisions[child].edits.clone();
+            for edit in &edits {
+                self.shift_marks(edit);
+            }
+        }
+        match self.history.redo(&mut self.file_text) {
+            Some(cursor) => {
+                self.restore_cursor(cursor);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn restore_cursor(&mut self, char_idx: usize) {
+        self.update_numbar_space();
+        let char_idx = char_idx.min(self.file_text.len_chars());
+        self.current_position = self.char_idx_to_position(char_idx);
+        self.ensure_cursor_visible();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(rope: &mut Rope, history: &mut History, char_idx: usize, text: &str) {
+        rope.insert(char_idx, text);
+        history.record(
+            Edit {
+                char_idx,
+                removed: String::new(),
+                inserted: text.to_string(),
+            },
+            char_idx,
+        );
+    }
+
+    #[test]
+    fn test_undo_redo_insert_session() {
+        let mut rope = Rope::from_str("fn main\n");
+        let mut history = History::new();
+
+        insert(&mut rope, &mut history, 7, "(");
+        insert(&mut rope, &mut history, 8, ")");
+        history.commit(9);
+        assert_eq!(history.revisions.len(), 2);
+        assert_eq!(history.revisions[1].edits.len(), 1);
+
+        assert_eq!(history.undo(&mut rope), Some(7));
+        assert_eq!(rope.to_string(), "fn main\n");
+        assert_eq!(history.undo(&mut rope), None);
+
+        assert_eq!(history.redo(&mut rope), Some(9));
+        assert_eq!(rope.to_string(), "fn main()\n");
+        assert_eq!(history.redo(&mut rope), None);
+    }
+
+    #[test]
+    fn test_undo_tree_keeps_branches() {
+        let mut rope = Rope::from_str("a\n");
+        let mut history = History::new();
+
+        insert(&mut rope, &mut history, 1, "b");
+        history.commit(2);
+        history.undo(&mut rope);
+
+        insert(&mut rope, &mut history, 1, "c");
+        history.commit(2);
+        assert_eq!(rope.to_string(), "ac\n");
+        assert_eq!(history.revisions[0].last_child, Some(2));
+
+        history.undo(&mut rope);
+        history.redo(&mut rope);
+        assert_eq!(rope.to_string(), "ac\n");
+        // The first branch is still in the tree.
+        assert_eq!(history.revisions[1].parent, 0);
+    }
+}
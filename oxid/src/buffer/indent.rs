@@ -0,0 +1,134 @@
+use super::core::Buffer;
+
+const OPENING_BRACKETS: [char; 3] = ['{', '(', '['];
+const CLOSING_BRACKETS: [char; 3] = ['}', ')', ']'];
+
+impl Buffer {
+    /// One level of indentation, a tab or `shiftwidth` spaces.
+    pub fn indent_unit(&self) -> String {
+        if self.settings.expandtab {
+            " ".repeat(self.settings.shiftwidth)
+        } else {
+            String::from('\t')
+        }
+    }
+
+    /// Tab in Insert mode, a tab char or, with `expandtab`, spaces up to the next indent
+    /// level.
+    pub fn insert_tab(&mut self) {
+        if !self.settings.expandtab {
+            self.insert_char('\t');
+            return;
+        }
+        let shiftwidth = self.settings.shiftwidth.max(1);
+        let spaces = shiftwidth - self.cursor_column() % shiftwidth;
+        for _ in 0..spaces {
+            self.insert_char(' ');
+        }
+    }
+
+    /// Leading whitespace of a line.
+    pub fn line_indent(&self, line: usize) -> String {
+        self.file_text
+            .line(line)
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect()
+    }
+
+    /// Indent of a new line following `text`, the start of `line` up to where it's split.
+    /// It's the indent of `line`, one level more after an opening bracket (or a ":" in
+    /// Python).
+    pub(super) fn indent_after(&self, line: usize, text: &str) -> String {
+        let mut indent = self.line_indent(line);
+        let last = text.trim_end().chars().last();
+        if last.is_some_and(|c| OPENING_BRACKETS.contains(&c))
+            || (last == Some(':') && self.filetype() == Some("py"))
+        {
+            indent.push_str(&self.indent_unit());
+        }
+        indent
+    }
+
+    /// Typing a closing bracket first on a line gives the line the indent of the line with
+    /// the matching opening bracket.
+    pub(super) fn dedent_closing_bracket(&mut self, ch: char) {
+        let Some(kind) = CLOSING_BRACKETS.iter().position(|c| *c == ch) else {
+            return;
+        };
+        let line = self.current_position.line;
+        let column = self.current_position.character - self.numbar_space;
+        let indent = self.line_indent(line);
+        if column != indent.chars().count() || indent.is_empty() {
+            return;
+        }
+
+        let line_start = self.file_text.line_to_char(line);
+        let new_indent = match self.matching_opening_bracket(line_start, kind) {
+            Some(char_idx) => self.line_indent(self.file_text.char_to_line(char_idx)),
+            None => {
+                let unit = self.indent_unit();
+                indent.strip_suffix(&unit).unwrap_or_default().to_string()
+            }
+        };
+        self.remove_text(line_start..line_start + column);
+        self.insert_text(line_start, &new_indent);
+        self.current_position.character = new_indent.chars().count() + self.numbar_space;
+    }
+
+    /// Char index of the unclosed opening bracket of the given kind before `char_idx`.
+    fn matching_opening_bracket(&self, char_idx: usize, kind: usize) -> Option<usize> {
+        let mut depth = 0;
+        let mut idx = char_idx;
+        for c in self.file_text.chars_at(char_idx).reversed() {
+            idx -= 1;
+            if c == CLOSING_BRACKETS[kind] {
+                depth += 1;
+            } else if c == OPENING_BRACKETS[kind] {
+                if depth == 0 {
+                    return Some(idx);
+                }
+                depth -= 1;
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::*;
+
+    #[test]
+    fn test_auto_indent() {
+        let mut buffer = Buffer::new(
+            Some("main.rs".to_string()),
+            Rope::from_str("fn main() {"),
+            80,
+            24,
+        );
+        buffer.current_position.character += 11;
+        buffer.enter_key();
+        assert_eq!(buffer.file_text.to_string(), "fn main() {\n    ");
+        buffer.insert_char('(');
+        buffer.enter_key();
+        buffer.insert_char(')');
+        assert_eq!(buffer.file_text.to_string(), "fn main() {\n    (\n    )");
+        buffer.enter_key();
+        buffer.insert_char('}');
+        assert_eq!(buffer.file_text.to_string(), "fn main() {\n    (\n    )\n}");
+
+        let mut buffer = Buffer::new(
+            Some("main.py".to_string()),
+            Rope::from_str("def main():\n"),
+            80,
+            24,
+        );
+        buffer.settings.expandtab = false;
+        buffer.insert_line_below();
+        assert_eq!(buffer.file_text.to_string(), "def main():\n\t\n");
+        assert_eq!(buffer.current_position.character, buffer.numbar_space + 1);
+    }
+}
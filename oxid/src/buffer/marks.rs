@@ -0,0 +1,58 @@
+use super::core::Buffer;
+use super::history::Edit;
+
+impl Buffer {
+    pub fn set_mark(&mut self, mark: char, char_idx: usize) {
+        self.marks.insert(mark, char_idx);
+    }
+
+    /// Char index of a mark, clamped to the text in case it was deleted.
+    pub fn mark(&self, mark: char) -> Option<usize> {
+        self.marks
+            .get(&mark)
+            .map(|char_idx| (*char_idx).min(self.file_text.len_chars()))
+    }
+
+    /// Moves the marks after an edit so they stay on the same text. Marks inside removed
+    /// text end up where it was.
+    pub(super) fn shift_marks(&mut self, edit: &Edit) {
+        let removed_end = edit.char_idx + edit.removed.chars().count();
+        let inserted_len = edit.inserted.chars().count();
+        for char_idx in self.marks.values_mut() {
+            if *char_idx >= removed_end {
+                *char_idx = *char_idx - (removed_end - edit.char_idx) + inserted_len;
+            } else if *char_idx > edit.char_idx {
+                *char_idx = edit.char_idx;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::*;
+
+    #[test]
+    fn test_marks_follow_lines() {
+        let mut buffer = Buffer::new(None, Rope::from_str("one\ntwo\nthree\n"), 80, 24);
+        buffer.set_mark('a', 8); // "three"
+        buffer.set_mark('b', 5); // "wo"
+
+        buffer.insert_text(0, "zero\n");
+        assert_eq!(buffer.mark('a'), Some(13));
+        buffer.commit_history();
+        buffer.remove_text(5..9);
+        assert_eq!(buffer.mark('a'), Some(9));
+        assert_eq!(buffer.mark('b'), Some(6));
+
+        buffer.commit_history();
+        buffer.undo();
+        assert_eq!(buffer.mark('a'), Some(13));
+        buffer.undo();
+        assert_eq!(buffer.mark('a'), Some(8));
+        buffer.redo();
+        assert_eq!(buffer.mark('a'), Some(13));
+    }
+}
@@ -1,7 +1,97 @@
 use super::core::Buffer;
-use super::types::{BufferPosition, CharType};
+use super::types::{BufferPosition, CharSearch, CharType, Motion};
 
 impl Buffer {
+    pub fn apply_motion(&mut self, motion: Motion) {
+        match motion {
+            Motion::Left => self.move_cursor_left(),
+            Motion::Down => self.move_cursor_down(),
+            Motion::Up => self.move_cursor_up(),
+            Motion::Right => self.move_cursor_right(),
+            Motion::DisplayDown => self.move_display_row(true),
+            Motion::DisplayUp => self.move_display_row(false),
+            Motion::NextWord => self.move_to_next_word(),
+            Motion::PreviousWord => self.move_to_previous_word(),
+            Motion::EndOfWord => self.move_to_end_of_word(),
+            Motion::LineStart => self.move_cursor_start_line(),
+            Motion::LineEnd => self.move_cursor_end_line(),
+            Motion::FirstNonBlank => self.move_to_first_non_blank(),
+            Motion::FindChar(search) => {
+                self.last_char_search = Some(search);
+                self.find_char(search, false);
+            }
+            Motion::RepeatFind { reverse } => {
+                if let Some(mut search) = self.last_char_search {
+                    search.forward ^= reverse;
+                    self.find_char(search, true);
+                }
+            }
+            Motion::ParagraphBackward => self.move_to_paragraph(false),
+            Motion::ParagraphForward => self.move_to_paragraph(true),
+            Motion::MatchingBracket => self.move_to_matching_bracket(),
+            Motion::FileStart => self.move_to_line(0),
+            Motion::FileEnd => self.move_to_line(self.last_line()),
+            Motion::ScreenTop => self.move_to_line(self.screen_lines().0),
+            Motion::ScreenMiddle => {
+                let (top, bottom) = self.screen_lines();
+                self.move_to_line((top + bottom) / 2);
+            }
+            Motion::ScreenBottom => self.move_to_line(self.screen_lines().1),
+        }
+    }
+
+    /// Applies a motion `count` times, except for the motions where the count is a line
+    /// number: "5G" goes to the fifth line and "3H" to the third one on screen.
+    pub fn apply_motion_count(&mut self, motion: Motion, count: Option<usize>) {
+        let Some(count) = count else {
+            self.apply_motion(motion);
+            return;
+        };
+        let (top, bottom) = self.screen_lines();
+        match motion {
+            Motion::FileStart | Motion::FileEnd => self.move_to_line(count - 1),
+            Motion::ScreenTop => self.move_to_line((top + count - 1).min(bottom)),
+            Motion::ScreenBottom => self.move_to_line(bottom.saturating_sub(count - 1).max(top)),
+            // The count goes on to the next chars, like ";" does.
+            Motion::FindChar(_) => {
+                self.apply_motion(motion);
+                for _ in 1..count {
+                    self.apply_motion(Motion::RepeatFind { reverse: false });
+                }
+            }
+            _ => {
+                for _ in 0..count {
+                    self.apply_motion(motion);
+                }
+            }
+        }
+    }
+
+    /// Moves to the char of `search` on the cursor line, if it's there.
+    pub fn find_char(&mut self, search: CharSearch, repeat: bool) {
+        let Some(chars) = self.get_line_chars(self.current_position.line) else {
+            return;
+        };
+        let len = self.line_content_len(self.current_position.line);
+        let column = self.current_position.character - self.numbar_space;
+        // Repeating "t" would find the char it stopped before again, so it starts past it.
+        let skip = usize::from(search.till && repeat);
+        let target = if search.forward {
+            (column + 1 + skip..len)
+                .find(|idx| chars[*idx] == search.ch)
+                .map(|idx| idx - usize::from(search.till))
+        } else {
+            (0..column.saturating_sub(skip))
+                .rev()
+                .find(|idx| chars[*idx] == search.ch)
+                .map(|idx| idx + usize::from(search.till))
+        };
+        if let Some(target) = target {
+            self.current_position.character = target + self.numbar_space;
+            self.ensure_cursor_visible();
+        }
+    }
+
     pub fn move_to_end_of_word(&mut self) {
         if let Some(position) = self.get_end_of_word() {
             self.current_position = position;
@@ -75,12 +165,15 @@ impl Buffer {
 
     pub fn move_cursor_left(&mut self) {
         if self.current_position.character > self.numbar_space {
-            self.current_position.character = self.current_position.character.saturating_sub(1);
+            let column = self.current_position.character - self.numbar_space;
+            self.current_position.character =
+                self.previous_grapheme(self.current_position.line, column) + self.numbar_space;
         }
         self.ensure_cursor_visible();
     }
 
     pub fn move_cursor_down(&mut self) {
+        let display_col = self.cursor_column();
         // If current line is bigger or eq than num of total lines, limit
         // it to last line available. Must be len_lines() - 1 because lines start at 0.
         self.current_position.line = {
@@ -90,6 +183,7 @@ impl Buffer {
                 self.current_position.line.saturating_add(1)
             }
         };
+        self.move_to_display_column(display_col);
 
         // Edge case where when going down, the line is empty line. Then put cursor
         // right after numbar.
@@ -108,7 +202,9 @@ impl Buffer {
     }
 
     pub fn move_cursor_up(&mut self) {
+        let display_col = self.cursor_column();
         self.current_position.line = self.current_position.line.saturating_sub(1);
+        self.move_to_display_column(display_col);
         // Edge case where when going up the line is empty line. Then put cursor
         // after numbar.
         if self.file_text.line(self.current_position.line).len_chars() == 0 {
@@ -126,27 +222,73 @@ impl Buffer {
         self.ensure_cursor_visible();
     }
 
+    /// "gj" and "gk", moves a display row down or up, within a wrapped line or to the next
+    /// or previous one, keeping the column on screen. Without soft wrap, like "j" and "k".
+    pub fn move_display_row(&mut self, down: bool) {
+        if !self.settings.wrap {
+            if down {
+                self.move_cursor_down();
+            } else {
+                self.move_cursor_up();
+            }
+            return;
+        }
+
+        let width = self.wrap_width();
+        let line = self.current_position.line;
+        let column = self.cursor_column();
+        let row = column / width;
+        let (line, column) = if down && row + 1 < self.line_rows(line) {
+            (line, column + width)
+        } else if down && line < self.last_line() {
+            (line + 1, column % width)
+        } else if !down && row > 0 {
+            (line, column - width)
+        } else if !down && line > 0 {
+            let last_row = self.line_width(line - 1).saturating_sub(1) / width;
+            (line - 1, last_row * width + column % width)
+        } else {
+            return;
+        };
+        let max_column = self.line_content_len(line).saturating_sub(1);
+        self.current_position = BufferPosition {
+            line,
+            character: self.column_at_display(line, column).min(max_column) + self.numbar_space,
+        };
+        self.ensure_cursor_visible();
+    }
+
+    /// Puts the cursor on the char drawn at a display column of its line, so that "j" and
+    /// "k" keep it on screen above or below where it was, across tabs.
+    fn move_to_display_column(&mut self, display_col: usize) {
+        self.current_position.character =
+            self.column_at_display(self.current_position.line, display_col) + self.numbar_space;
+    }
+
     pub fn move_cursor_right(&mut self) {
         let line_len = self.file_text.line(self.current_position.line).len_chars();
         // For some reason the -1 must be on this line and not after len_chars() otherwise it
         // crashes if cursor is on last line, it's empty and tries to go to the right.
         let max_cursor_pos = line_len + self.numbar_space - 1;
-        if self.current_position.character < max_cursor_pos {
-            self.current_position.character = self.current_position.character.saturating_add(1);
+        let column = self.current_position.character - self.numbar_space;
+        let next = self.next_grapheme(self.current_position.line, column) + self.numbar_space;
+        if next <= max_cursor_pos {
+            self.current_position.character = next;
         }
         self.ensure_cursor_visible();
     }
 
     pub fn insert_line_below(&mut self) {
         let curr_line = self.current_position.line;
-        let curr_idx = self.file_text.line_to_char(curr_line + 1);
-
-        self.file_text.insert(curr_idx, "\n");
+        let line_start = self.file_text.line_to_char(curr_line);
+        let line_end = line_start + self.line_content_len(curr_line);
+        let line_text = self.file_text.slice(line_start..line_end).to_string();
+        let indent = self.indent_after(curr_line, &line_text);
 
-        self.update_numbar_space();
+        self.insert_text(line_end, &format!("\n{indent}"));
 
         self.current_position.line = self.current_position.line.saturating_add(1);
-        self.current_position.character = self.numbar_space;
+        self.current_position.character = indent.chars().count() + self.numbar_space;
         self.ensure_cursor_visible();
     }
 
@@ -158,28 +300,28 @@ impl Buffer {
             .saturating_sub(self.numbar_space);
 
         // Try current line first
-        if let Some(chars) = self.get_line_chars(line_idx) {
-            if char_idx < chars.len() {
-                let current_type = CharType::from_char(chars[char_idx]);
-                let mut pos = char_idx;
-
-                // Skip characters of same type if not whitespace
-                if current_type != CharType::Whitespace {
-                    while pos < chars.len() && CharType::from_char(chars[pos]) == current_type {
-                        pos += 1;
-                    }
+        if let Some(chars) = self.get_line_chars(line_idx)
+            && char_idx < chars.len()
+        {
+            let current_type = CharType::from_char(chars[char_idx]);
+            let mut pos = char_idx;
+
+            // Skip characters of same type if not whitespace
+            if current_type != CharType::Whitespace {
+                while pos < chars.len() && CharType::from_char(chars[pos]) == current_type {
+                    pos += 1;
                 }
+            }
 
-                // Skip whitespace
-                pos = self.skip_whitespace_forward(&chars, pos);
+            // Skip whitespace
+            pos = self.skip_whitespace_forward(&chars, pos);
 
-                // Return position if we found non-whitespace
-                if pos < chars.len() {
-                    return Some(BufferPosition {
-                        line: line_idx,
-                        character: pos + self.numbar_space,
-                    });
-                }
+            // Return position if we found non-whitespace
+            if pos < chars.len() {
+                return Some(BufferPosition {
+                    line: line_idx,
+                    character: pos + self.numbar_space,
+                });
             }
         }
 
@@ -221,22 +363,23 @@ impl Buffer {
             .saturating_sub(self.numbar_space);
 
         // Try current line first
-        if let Some(chars) = self.get_line_chars(line_idx) {
-            if char_idx > 0 {
-                let mut pos = char_idx - 1;
+        if let Some(chars) = self.get_line_chars(line_idx)
+            && char_idx > 0
+        {
+            let mut pos = char_idx - 1;
 
-                // Skip whitespace backward
-                pos = self.skip_whitespace_backward(&chars, pos);
+            // Skip whitespace backward
+            pos = self.skip_whitespace_backward(&chars, pos);
 
-                // If we found non-whitespace, find start of word
-                if pos < chars.len() && !chars[pos].is_whitespace() {
-                    if let Some(word_start) = self.find_word_start(&chars, pos) {
-                        return Some(BufferPosition {
-                            line: line_idx,
-                            character: word_start + self.numbar_space,
-                        });
-                    }
-                }
+            // If we found non-whitespace, find start of word
+            if pos < chars.len()
+                && !chars[pos].is_whitespace()
+                && let Some(word_start) = self.find_word_start(&chars, pos)
+            {
+                return Some(BufferPosition {
+                    line: line_idx,
+                    character: word_start + self.numbar_space,
+                });
             }
         }
 
@@ -254,13 +397,14 @@ impl Buffer {
                 let mut pos = chars.len() - 1;
                 pos = self.skip_whitespace_backward(&chars, pos);
 
-                if pos < chars.len() && !chars[pos].is_whitespace() {
-                    if let Some(word_start) = self.find_word_start(&chars, pos) {
-                        return Some(BufferPosition {
-                            line: prev_line_idx,
-                            character: word_start + self.numbar_space,
-                        });
-                    }
+                if pos < chars.len()
+                    && !chars[pos].is_whitespace()
+                    && let Some(word_start) = self.find_word_start(&chars, pos)
+                {
+                    return Some(BufferPosition {
+                        line: prev_line_idx,
+                        character: word_start + self.numbar_space,
+                    });
                 }
 
                 // Line with only whitespace
@@ -282,38 +426,38 @@ impl Buffer {
             .saturating_sub(self.numbar_space);
 
         // Try current line first
-        if let Some(chars) = self.get_line_chars(line_idx) {
-            if char_idx < chars.len() {
-                let current_type = CharType::from_char(chars[char_idx]);
-
-                if current_type == CharType::Whitespace {
-                    // Skip whitespace and find end of next word
-                    let pos = self.skip_whitespace_forward(&chars, char_idx);
-                    if let Some(word_end) = self.find_word_end(&chars, pos) {
-                        return Some(BufferPosition {
-                            line: line_idx,
-                            character: word_end + self.numbar_space,
-                        });
-                    }
-                } else if self.is_at_word_end(&chars, char_idx) {
-                    // At word end, find next word's end
-                    let mut pos = char_idx + 1;
-                    pos = self.skip_whitespace_forward(&chars, pos);
-
-                    if let Some(word_end) = self.find_word_end(&chars, pos) {
-                        return Some(BufferPosition {
-                            line: line_idx,
-                            character: word_end + self.numbar_space,
-                        });
-                    }
-                } else {
-                    // In middle of word, find end of current word
-                    if let Some(word_end) = self.find_word_end(&chars, char_idx) {
-                        return Some(BufferPosition {
-                            line: line_idx,
-                            character: word_end + self.numbar_space,
-                        });
-                    }
+        if let Some(chars) = self.get_line_chars(line_idx)
+            && char_idx < chars.len()
+        {
+            let current_type = CharType::from_char(chars[char_idx]);
+
+            if current_type == CharType::Whitespace {
+                // Skip whitespace and find end of next word
+                let pos = self.skip_whitespace_forward(&chars, char_idx);
+                if let Some(word_end) = self.find_word_end(&chars, pos) {
+                    return Some(BufferPosition {
+                        line: line_idx,
+                        character: word_end + self.numbar_space,
+                    });
+                }
+            } else if self.is_at_word_end(&chars, char_idx) {
+                // At word end, find next word's end
+                let mut pos = char_idx + 1;
+                pos = self.skip_whitespace_forward(&chars, pos);
+
+                if let Some(word_end) = self.find_word_end(&chars, pos) {
+                    return Some(BufferPosition {
+                        line: line_idx,
+                        character: word_end + self.numbar_space,
+                    });
+                }
+            } else {
+                // In middle of word, find end of current word
+                if let Some(word_end) = self.find_word_end(&chars, char_idx) {
+                    return Some(BufferPosition {
+                        line: line_idx,
+                        character: word_end + self.numbar_space,
+                    });
                 }
             }
         }
@@ -338,25 +482,46 @@ impl Buffer {
         None
     }
 
-    fn get_line_chars(&self, line_idx: usize) -> Option<Vec<char>> {
+    /// Char index right after the end of the word under the cursor, if the cursor is on one.
+    pub fn word_end_at_cursor(&self) -> Option<usize> {
+        let line_idx = self.current_position.line;
+        let char_idx = self
+            .current_position
+            .character
+            .saturating_sub(self.numbar_space);
+        let chars = self.get_line_chars(line_idx)?;
+        let word_end = self.find_word_end(&chars, char_idx)?;
+        Some(self.file_text.line_to_char(line_idx) + word_end + 1)
+    }
+
+    /// Char offset of the first non-whitespace character of the line, or of its end.
+    pub fn first_non_blank(&self, line_idx: usize) -> usize {
+        self.file_text
+            .line(line_idx)
+            .chars()
+            .take_while(|c| c.is_whitespace() && *c != '\n')
+            .count()
+    }
+
+    pub(super) fn get_line_chars(&self, line_idx: usize) -> Option<Vec<char>> {
         Some(self.file_text.line(line_idx).chars().collect())
     }
 
-    fn skip_whitespace_forward(&self, chars: &[char], mut pos: usize) -> usize {
+    pub(super) fn skip_whitespace_forward(&self, chars: &[char], mut pos: usize) -> usize {
         while pos < chars.len() && chars[pos].is_whitespace() {
             pos += 1;
         }
         pos
     }
 
-    fn skip_whitespace_backward(&self, chars: &[char], mut pos: usize) -> usize {
+    pub(super) fn skip_whitespace_backward(&self, chars: &[char], mut pos: usize) -> usize {
         while pos > 0 && chars[pos].is_whitespace() {
             pos -= 1;
         }
         pos
     }
 
-    fn find_word_end(&self, chars: &[char], start: usize) -> Option<usize> {
+    pub(super) fn find_word_end(&self, chars: &[char], start: usize) -> Option<usize> {
         if start >= chars.len() {
             return None;
         }
@@ -373,7 +538,7 @@ impl Buffer {
         Some(pos - 1)
     }
 
-    fn find_word_start(&self, chars: &[char], start: usize) -> Option<usize> {
+    pub(super) fn find_word_start(&self, chars: &[char], start: usize) -> Option<usize> {
         if start >= chars.len() {
             return None;
         }
@@ -411,3 +576,31 @@ impl Buffer {
         pos + 1 >= chars.len() || CharType::from_char(chars[pos + 1]) != current_type
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use crate::buffer::Buffer;
+    use crate::buffer::types::{CharSearch, Motion};
+
+    #[test]
+    fn test_till_char() {
+        let mut buffer = Buffer::new(None, Rope::from_str("axxbx\n"), 80, 24);
+        let till_x = Motion::FindChar(CharSearch {
+            ch: 'x',
+            forward: true,
+            till: true,
+        });
+        // "tx" again stays before the next "x", only ";" goes past it.
+        buffer.apply_motion(till_x);
+        buffer.apply_motion(till_x);
+        assert_eq!(buffer.current_position.character - buffer.numbar_space, 0);
+        buffer.apply_motion(Motion::RepeatFind { reverse: false });
+        assert_eq!(buffer.current_position.character - buffer.numbar_space, 1);
+
+        buffer.apply_motion(Motion::LineStart);
+        buffer.apply_motion_count(till_x, Some(3));
+        assert_eq!(buffer.current_position.character - buffer.numbar_space, 3);
+    }
+}
@@ -0,0 +1,205 @@
+use super::core::Buffer;
+use super::types::BufferPosition;
+
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+/// Which chars of a line are inside a string or char literal. Quotes that aren't closed on
+/// the line (e.g. Rust lifetimes) don't start one.
+fn string_mask(chars: &[char]) -> Vec<bool> {
+    let mut mask = vec![false; chars.len()];
+    let mut idx = 0;
+    while idx < chars.len() {
+        let end = match chars[idx] {
+            '"' => (idx + 1..chars.len()).find(|end| chars[*end] == '"' && chars[end - 1] != '\\'),
+            // Only short char literals, 'x' or '\n', for the languages where "'" is also
+            // used alone.
+            '\'' => match chars.get(idx + 1..) {
+                Some(['\\', _, '\'', ..]) => Some(idx + 3),
+                Some([_, '\'', ..]) => Some(idx + 2),
+                _ => None,
+            },
+            _ => None,
+        };
+        match end {
+            Some(end) => {
+                mask[idx..=end].fill(true);
+                idx = end + 1;
+            }
+            None => idx += 1,
+        }
+    }
+    mask
+}
+
+impl Buffer {
+    /// The last line of the file, not counting the empty one after a final line break.
+    pub fn last_line(&self) -> usize {
+        let last_line = self.file_text.len_lines() - 1;
+        if last_line > 0 && self.file_text.line(last_line).len_chars() == 0 {
+            last_line - 1
+        } else {
+            last_line
+        }
+    }
+
+    /// "gg" and "G", moves to the first non-blank char of a line.
+    pub fn move_to_line(&mut self, line: usize) {
+        let line = line.min(self.last_line());
+        self.current_position = BufferPosition {
+            line,
+            character: self.first_non_blank(line) + self.numbar_space,
+        };
+        self.ensure_cursor_visible();
+    }
+
+    /// "^", moves to the first non-blank char of the cursor line.
+    pub fn move_to_first_non_blank(&mut self) {
+        self.current_position.character =
+            self.first_non_blank(self.current_position.line) + self.numbar_space;
+        self.ensure_cursor_visible();
+    }
+
+    /// First and last line shown on screen.
+    pub fn screen_lines(&self) -> (usize, usize) {
+        let first = self.vertical_scroll;
+        let mut last = (first + self.viewport_height).saturating_sub(1);
+        if self.settings.wrap {
+            // Wrapped lines take more rows, only count the lines that start on screen.
+            let mut rows = 0;
+            last = first;
+            while last < self.last_line() && rows + self.line_rows(last) < self.viewport_height {
+                rows += self.line_rows(last);
+                last += 1;
+            }
+        }
+        (first, last.min(self.last_line()))
+    }
+
+    /// "{" and "}", moves to the blank line before or after the paragraph. With no blank line
+    /// left, it stops at the start or end of the file.
+    pub fn move_to_paragraph(&mut self, forward: bool) {
+        let is_blank = |line: usize| self.file_text.line(line).chars().all(|c| c.is_whitespace());
+        let last_line = self.last_line();
+        let mut line = self.current_position.line;
+        if forward {
+            while line < last_line && is_blank(line) {
+                line += 1;
+            }
+            while line < last_line && !is_blank(line) {
+                line += 1;
+            }
+        } else {
+            while line > 0 && is_blank(line) {
+                line -= 1;
+            }
+            while line > 0 && !is_blank(line) {
+                line -= 1;
+            }
+        }
+
+        let column = if forward && !is_blank(line) {
+            self.line_content_len(line)
+        } else {
+            0
+        };
+        self.current_position = BufferPosition {
+            line,
+            character: column + self.numbar_space,
+        };
+        self.ensure_cursor_visible();
+    }
+
+    /// "%", moves to the bracket matching the one under the cursor, or the first one after
+    /// it on the line.
+    pub fn move_to_matching_bracket(&mut self) {
+        if let Some(position) = self.matching_bracket() {
+            self.current_position = position;
+            self.ensure_cursor_visible();
+        }
+    }
+
+    fn matching_bracket(&self) -> Option<BufferPosition> {
+        let start_line = self.current_position.line;
+        let chars = self.get_line_chars(start_line)?;
+        let column = self.current_position.character - self.numbar_space;
+        let (start, forward, target, nested) = (column..chars.len()).find_map(|idx| {
+            BRACKETS.iter().find_map(|&(open, close)| {
+                if chars[idx] == open {
+                    Some((idx, true, close, open))
+                } else if chars[idx] == close {
+                    Some((idx, false, open, close))
+                } else {
+                    None
+                }
+            })
+        })?;
+        // Brackets in strings are skipped, unless the search starts in one.
+        let skip_strings = !string_mask(&chars)[start];
+
+        let last_line = self.file_text.len_lines() - 1;
+        let mut depth = 0;
+        let mut line = start_line;
+        loop {
+            let chars = self.get_line_chars(line)?;
+            let mask = string_mask(&chars);
+            let columns: Vec<usize> = match (forward, line == start_line) {
+                (true, true) => (start + 1..chars.len()).collect(),
+                (true, false) => (0..chars.len()).collect(),
+                (false, true) => (0..start).rev().collect(),
+                (false, false) => (0..chars.len()).rev().collect(),
+            };
+            for idx in columns {
+                if skip_strings && mask[idx] {
+                    continue;
+                }
+                if chars[idx] == nested {
+                    depth += 1;
+                } else if chars[idx] == target {
+                    if depth == 0 {
+                        return Some(BufferPosition {
+                            line,
+                            character: idx + self.numbar_space,
+                        });
+                    }
+                    depth -= 1;
+                }
+            }
+
+            if forward && line < last_line {
+                line += 1;
+            } else if !forward && line > 0 {
+                line -= 1;
+            } else {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::*;
+
+    #[test]
+    fn test_matching_bracket() {
+        let mut buffer = Buffer::new(
+            None,
+            Rope::from_str("if (a == \")\") {\n    f(b[0], ')');\n}\n"),
+            80,
+            24,
+        );
+        buffer.move_to_matching_bracket();
+        assert_eq!(buffer.cursor_char_idx(), 12);
+        buffer.move_to_matching_bracket();
+        assert_eq!(buffer.cursor_char_idx(), 3);
+
+        buffer.move_to_line(1);
+        buffer.move_to_matching_bracket();
+        assert_eq!(buffer.cursor_char_idx(), 31);
+        buffer.move_to_line(2);
+        buffer.move_to_matching_bracket();
+        assert_eq!(buffer.cursor_char_idx(), 14);
+    }
+}
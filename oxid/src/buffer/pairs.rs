@@ -0,0 +1,120 @@
+use super::core::Buffer;
+
+impl Buffer {
+    /// Auto-pairs of the filetype as (opening, closing) chars.
+    fn pairs(&self) -> impl Iterator<Item = (char, char)> + '_ {
+        self.settings.pairs.iter().filter_map(|pair| {
+            let mut chars = pair.chars();
+            match (chars.next(), chars.next(), chars.next()) {
+                (Some(open), Some(close), None) => Some((open, close)),
+                _ => None,
+            }
+        })
+    }
+
+    fn char_before_cursor(&self) -> Option<char> {
+        let char_idx = self.cursor_char_idx();
+        let column = self.current_position.character - self.numbar_space;
+        (column > 0).then(|| self.file_text.char(char_idx - 1))
+    }
+
+    pub(super) fn char_after_cursor(&self) -> Option<char> {
+        let char_idx = self.cursor_char_idx();
+        (char_idx < self.file_text.len_chars())
+            .then(|| self.file_text.char(char_idx))
+            .filter(|c| *c != '\n')
+    }
+
+    /// Types a char in Insert mode. An opening char gets its closing partner after the
+    /// cursor, and typing a closing char right before the same one steps over it.
+    pub fn type_char(&mut self, ch: char) {
+        let next = self.char_after_cursor();
+        let pair = self
+            .pairs()
+            .find(|(open, close)| *open == ch || *close == ch);
+        let Some((open, close)) = pair else {
+            self.insert_char(ch);
+            return;
+        };
+
+        if ch == close && next == Some(close) {
+            self.current_position.character += 1;
+            self.ensure_cursor_visible();
+            return;
+        }
+        // Only pair before blanks or closing chars, so that "(" in front of a word doesn't
+        // get in the way. Quotes are not paired after a word either, for "don't".
+        let closes_here = next.is_none_or(|c| c.is_whitespace() || self.pairs().any(|p| p.1 == c));
+        let after_word = self
+            .char_before_cursor()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        if ch == open && closes_here && !(open == close && after_word) {
+            self.insert_char(open);
+            let char_idx = self.cursor_char_idx();
+            self.insert_text(char_idx, &close.to_string());
+        } else {
+            self.insert_char(ch);
+        }
+    }
+
+    /// Backspace in Insert mode, deleting both chars of an empty pair.
+    pub fn backspace(&mut self) {
+        let (Some(before), Some(after)) = (self.char_before_cursor(), self.char_after_cursor())
+        else {
+            self.remove_char();
+            return;
+        };
+        if self.pairs().any(|pair| pair == (before, after)) {
+            let char_idx = self.cursor_char_idx();
+            self.remove_text(char_idx..char_idx + 1);
+        }
+        self.remove_char();
+    }
+
+    /// Enter in Insert mode. Between a pair of brackets, the closing one goes on its own
+    /// line and the cursor on an indented line in between.
+    pub fn enter(&mut self) {
+        let (Some(before), Some(after)) = (self.char_before_cursor(), self.char_after_cursor())
+        else {
+            self.enter_key();
+            return;
+        };
+        let line = self.current_position.line;
+        if before == after || !self.pairs().any(|pair| pair == (before, after)) {
+            self.enter_key();
+            return;
+        }
+        let indent = self.line_indent(line);
+        self.enter_key();
+        let char_idx = self.cursor_char_idx();
+        self.insert_text(char_idx, &format!("\n{indent}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::*;
+
+    #[test]
+    fn test_auto_pairs() {
+        let mut buffer = Buffer::new(None, Rope::from_str("\n"), 80, 24);
+        for ch in "if (x".chars() {
+            buffer.type_char(ch);
+        }
+        buffer.type_char(')');
+        assert_eq!(buffer.file_text.to_string(), "if (x)\n");
+        buffer.type_char(' ');
+        buffer.type_char('{');
+        buffer.enter();
+        buffer.type_char('"');
+        assert_eq!(buffer.file_text.to_string(), "if (x) {\n    \"\"\n}\n");
+        buffer.backspace();
+        assert_eq!(buffer.file_text.to_string(), "if (x) {\n    \n}\n");
+        for ch in "don't".chars() {
+            buffer.type_char(ch);
+        }
+        assert_eq!(buffer.file_text.to_string(), "if (x) {\n    don't\n}\n");
+    }
+}
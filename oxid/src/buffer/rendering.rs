@@ -3,6 +3,15 @@ use super::types::BufferPosition;
 
 impl Buffer {
     pub fn ensure_cursor_visible(&mut self) {
+        if self.settings.wrap {
+            self.ensure_cursor_row_visible();
+            return;
+        }
+        // The cursor is drawn on whole graphemes, not on the combining chars inside them.
+        let column = self.current_position.character - self.numbar_space;
+        self.current_position.character =
+            self.grapheme_start(self.current_position.line, column) + self.numbar_space;
+
         let cursor_line = self.current_position.line;
         let viewport_bottom = self.vertical_scroll + self.viewport_height;
 
@@ -13,51 +22,224 @@ impl Buffer {
             self.vertical_scroll = cursor_line.saturating_sub(self.viewport_height) + 1;
         }
 
-        // Horizontal scrolling
-        let cursor_char = self.current_position.character;
-        let viewport_right = self.horizontal_scroll + self.viewport_width + self.numbar_space;
+        // Horizontal scrolling, in display columns
+        let cursor_col = self.cursor_column();
 
         // If cursor is to the left of visible area, scroll left
-        if cursor_char < self.horizontal_scroll + self.numbar_space {
-            self.horizontal_scroll = cursor_char.saturating_sub(self.numbar_space);
+        if cursor_col < self.horizontal_scroll {
+            self.horizontal_scroll = cursor_col;
         }
         // If cursor is to the right of visible area, scroll right
-        else if cursor_char >= viewport_right {
-            self.horizontal_scroll = cursor_char
-                .saturating_sub(self.viewport_width)
-                .saturating_sub(self.numbar_space)
-                + 1;
+        else if cursor_col >= self.horizontal_scroll + self.viewport_width {
+            self.horizontal_scroll = (cursor_col + 1).saturating_sub(self.viewport_width);
         }
     }
 
-    pub fn get_visible_lines(&self) -> Vec<ropey::RopeSlice> {
+    pub fn get_visible_lines(&self) -> Vec<ropey::RopeSlice<'_>> {
         let start = self.vertical_scroll;
         let end = std::cmp::min(start + self.viewport_height, self.file_text.len_lines());
 
         (start..end).map(|i| self.file_text.line(i)).collect()
     }
 
-    pub fn get_visible_line_content(&self, line: ropey::RopeSlice) -> String {
-        let start_col = self.horizontal_scroll;
-        let line_len = line.len_chars();
-
-        if start_col >= line_len {
-            return String::new();
+    /// The cells of a line shown on screen, as the column of the char each one belongs to
+    /// and the text to draw. Tabs take the cells up to the next tab stop, drawn as spaces,
+    /// and the second cell of a wide char is left empty.
+    pub fn get_visible_line_content(&self, line: usize) -> Vec<(usize, String)> {
+        let mut cells = Vec::new();
+        let mut display_col = 0;
+        for (col, grapheme) in self.line_graphemes(line) {
+            let width = self.grapheme_width(&grapheme, display_col);
+            if grapheme == "\t" {
+                cells.extend(std::iter::repeat_n((col, String::from(" ")), width));
+            } else {
+                cells.push((col, grapheme));
+                cells.extend(std::iter::repeat_n((col, String::new()), width - 1));
+            }
+            display_col += width;
         }
-
-        let end_col = std::cmp::min(start_col + self.viewport_width, line_len);
-
-        // TODO: Check this unwrap
-        line.get_slice(start_col..end_col).unwrap().to_string()
+        // Wrapped lines are shown whole, split into rows by the editor view.
+        if self.settings.wrap {
+            return cells;
+        }
+        let mut cells: Vec<(usize, String)> = cells
+            .into_iter()
+            .skip(self.horizontal_scroll)
+            .take(self.viewport_width)
+            .collect();
+        // A wide char cut in half by the scroll.
+        if let Some((_, text)) = cells.first_mut()
+            && text.is_empty()
+        {
+            text.push(' ');
+        }
+        cells
     }
 
     pub fn get_viewport_cursor_pos(&self) -> BufferPosition {
+        if self.settings.wrap {
+            return BufferPosition {
+                line: self.cursor_row(),
+                character: self.cursor_column() % self.wrap_width() + self.numbar_space,
+            };
+        }
         BufferPosition {
             line: self.current_position.line - self.vertical_scroll,
-            character: self
-                .current_position
-                .character
+            character: (self.cursor_column() + self.numbar_space)
                 .saturating_sub(self.horizontal_scroll),
         }
     }
+
+    /// Display column of the char at `column` of a line, counting the cells of the
+    /// graphemes before it. Columns past the end of the line count one cell each.
+    pub fn display_column(&self, line: usize, column: usize) -> usize {
+        let mut display_col = 0;
+        let mut end = 0;
+        for (start, grapheme) in self.line_graphemes(line) {
+            if start >= column {
+                return display_col;
+            }
+            end = start + grapheme.chars().count();
+            if end > column {
+                // Inside a grapheme, which is drawn from its first cell.
+                return display_col;
+            }
+            display_col += self.grapheme_width(&grapheme, display_col);
+        }
+        display_col + column - end
+    }
+
+    /// Column of the grapheme drawn at `display_col` of a line, the reverse of
+    /// `display_column`.
+    pub fn column_at_display(&self, line: usize, display_col: usize) -> usize {
+        let mut cells_end = 0;
+        let mut end = 0;
+        for (start, grapheme) in self.line_graphemes(line) {
+            cells_end += self.grapheme_width(&grapheme, cells_end);
+            if display_col < cells_end {
+                return start;
+            }
+            end = start + grapheme.chars().count();
+        }
+        end + display_col - cells_end
+    }
+
+    /// Display width of a line, without its line break.
+    pub fn line_width(&self, line: usize) -> usize {
+        self.display_column(line, self.line_content_len(line))
+    }
+
+    /// Columns of a display row when soft wrap is on.
+    pub fn wrap_width(&self) -> usize {
+        self.viewport_width.max(1)
+    }
+
+    /// Display rows taken by a line, more than one when soft wrap is on and the line is
+    /// longer than the viewport. The cursor past the end of the line takes a cell too.
+    pub fn line_rows(&self, line: usize) -> usize {
+        if !self.settings.wrap {
+            return 1;
+        }
+        let mut len = self.line_width(line);
+        if line == self.current_position.line && self.cursor_column() >= len {
+            len += 1;
+        }
+        len.div_ceil(self.wrap_width()).max(1)
+    }
+
+    /// Display column of the cursor.
+    pub(super) fn cursor_column(&self) -> usize {
+        let column = self
+            .current_position
+            .character
+            .saturating_sub(self.numbar_space);
+        self.display_column(self.current_position.line, column)
+    }
+
+    /// Display row of the cursor, counted from the top of the viewport.
+    fn cursor_row(&self) -> usize {
+        let line = self.current_position.line;
+        let rows_above: usize = (self.vertical_scroll..line)
+            .map(|line| self.line_rows(line))
+            .sum();
+        rows_above + self.cursor_column() / self.wrap_width()
+    }
+
+    /// Scrolls by buffer lines until the display row of the cursor is on screen.
+    fn ensure_cursor_row_visible(&mut self) {
+        self.horizontal_scroll = 0;
+        let line = self.current_position.line;
+        if line < self.vertical_scroll {
+            self.vertical_scroll = line;
+        } else if line >= self.vertical_scroll + self.viewport_height {
+            // Every line takes a row at least, so no need to look at the lines above this.
+            self.vertical_scroll = line + 1 - self.viewport_height;
+        }
+        while self.vertical_scroll < line && self.cursor_row() >= self.viewport_height {
+            self.vertical_scroll += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::*;
+    use crate::buffer::types::Motion;
+
+    #[test]
+    fn test_soft_wrap_rows() {
+        let text = format!("{}\nshort\n", "x".repeat(25));
+        // 10 columns of text next to a number bar of 2.
+        let mut buffer = Buffer::new(None, Rope::from_str(&text), 12, 4);
+        buffer.settings.wrap = true;
+        assert_eq!(buffer.line_rows(0), 3);
+
+        buffer.apply_motion(Motion::DisplayDown);
+        buffer.apply_motion(Motion::DisplayDown);
+        assert_eq!(buffer.cursor_char_idx(), 20);
+        let cursor = buffer.get_viewport_cursor_pos();
+        assert_eq!((cursor.line, cursor.character), (2, buffer.numbar_space));
+
+        buffer.apply_motion(Motion::DisplayDown);
+        assert_eq!(buffer.current_position.line, 1);
+        // The three rows of the viewport are taken by the first line, so it scrolls.
+        assert_eq!(buffer.vertical_scroll, 1);
+        assert_eq!(buffer.get_viewport_cursor_pos().line, 0);
+        buffer.apply_motion(Motion::DisplayUp);
+        assert_eq!(buffer.cursor_char_idx(), 20);
+        assert_eq!(buffer.vertical_scroll, 0);
+    }
+
+    #[test]
+    fn test_tab_display_columns() {
+        let mut buffer = Buffer::new(None, Rope::from_str("\tab\tc\nabcdefghij\n"), 80, 24);
+        buffer.settings.tabstop = 4;
+        assert_eq!(buffer.display_column(0, 1), 4);
+        assert_eq!(buffer.display_column(0, 4), 8);
+        assert_eq!(buffer.column_at_display(0, 2), 0);
+        assert_eq!(buffer.column_at_display(0, 7), 3);
+        assert_eq!(buffer.line_width(0), 9);
+        let cells = buffer.get_visible_line_content(0);
+        let texts: Vec<&str> = cells[..5].iter().map(|(_, text)| text.as_str()).collect();
+        assert_eq!(texts, [" ", " ", " ", " ", "a"]);
+        assert_eq!((cells[3].0, cells[4].0), (0, 1));
+
+        // "j" keeps the cursor under the same cell.
+        buffer.apply_motion(Motion::Right);
+        buffer.apply_motion(Motion::Down);
+        assert_eq!(buffer.cursor_char_idx(), 6 + 4);
+        buffer.apply_motion(Motion::Right);
+        buffer.apply_motion(Motion::Right);
+        buffer.apply_motion(Motion::Up);
+        assert_eq!(buffer.cursor_char_idx(), 3);
+        let cursor = buffer.get_viewport_cursor_pos();
+        assert_eq!(cursor.character, 6 + buffer.numbar_space);
+
+        buffer.settings.expandtab = true;
+        buffer.settings.shiftwidth = 4;
+        buffer.insert_tab();
+        assert_eq!(buffer.file_text.line(0).to_string(), "\tab  \tc\n");
+    }
 }
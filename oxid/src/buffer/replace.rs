@@ -0,0 +1,114 @@
+use std::ops::Range;
+
+use super::core::Buffer;
+use super::types::Case;
+
+impl Buffer {
+    /// Types a char in Replace mode, overwriting the grapheme under the cursor.
+    pub fn replace_char(&mut self, ch: char) {
+        let char_idx = self.cursor_char_idx();
+        let line = self.current_position.line;
+        let column = self.current_position.character - self.numbar_space;
+        let original = self
+            .line_graphemes(line)
+            .into_iter()
+            .find(|(start, grapheme)| *start == column && !grapheme.starts_with(['\n', '\r']))
+            .map(|(_, grapheme)| grapheme);
+        if let Some(original) = &original {
+            self.remove_text(char_idx..char_idx + original.chars().count());
+        }
+        self.insert_text(char_idx, &ch.to_string());
+        self.replaced.push(original);
+        self.current_position.character += 1;
+        self.ensure_cursor_visible();
+    }
+
+    /// Enter in Replace mode, which splits the line without overwriting anything.
+    pub fn replace_enter(&mut self) {
+        let char_idx = self.cursor_char_idx();
+        self.insert_text(char_idx, "\n");
+        self.replaced.push(None);
+        self.current_position.line += 1;
+        self.current_position.character = self.numbar_space;
+        self.ensure_cursor_visible();
+    }
+
+    /// Backspace in Replace mode, putting back the grapheme that was overwritten. Before where
+    /// Replace mode started, it only moves left.
+    pub fn replace_backspace(&mut self) {
+        match self.replaced.pop() {
+            Some(Some(original)) => {
+                self.current_position.character -= 1;
+                let char_idx = self.cursor_char_idx();
+                self.remove_text(char_idx..char_idx + 1);
+                self.insert_text(char_idx, &original);
+            }
+            Some(None) => self.remove_char(),
+            None => self.move_cursor_left(),
+        }
+        self.ensure_cursor_visible();
+    }
+
+    /// "r", replaces `count` chars from the cursor with `ch`. Nothing changes if the line
+    /// doesn't have that many chars left.
+    pub fn replace_chars(&mut self, ch: char, count: usize) {
+        let line = self.current_position.line;
+        let column = self.current_position.character - self.numbar_space;
+        if column + count > self.line_content_len(line) {
+            return;
+        }
+        let char_idx = self.cursor_char_idx();
+        self.remove_text(char_idx..char_idx + count);
+        self.insert_text(char_idx, &ch.to_string().repeat(count));
+        self.current_position.character += count - 1;
+        self.ensure_cursor_visible();
+    }
+
+    /// "~", toggles the case of `count` chars from the cursor and moves past them.
+    pub fn toggle_case_at_cursor(&mut self, count: usize) {
+        let line = self.current_position.line;
+        let column = self.current_position.character - self.numbar_space;
+        let line_len = self.line_content_len(line);
+        let count = count.min(line_len.saturating_sub(column));
+        let char_idx = self.cursor_char_idx();
+        self.change_case(char_idx..char_idx + count, Case::Toggle);
+        self.current_position.character =
+            (column + count).min(line_len.saturating_sub(1)) + self.numbar_space;
+        self.ensure_cursor_visible();
+    }
+
+    /// Converts the case of the chars in `range`.
+    pub fn change_case(&mut self, range: Range<usize>, case: Case) {
+        let text = self.file_text.slice(range.clone()).to_string();
+        let converted = case.convert(&text);
+        if converted != text {
+            let start = range.start;
+            self.remove_text(range);
+            self.insert_text(start, &converted);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::*;
+
+    #[test]
+    fn test_replace_mode_backspace() {
+        let mut buffer = Buffer::new(None, Rope::from_str("abc\n"), 80, 24);
+        buffer.current_position.character += 1;
+        for ch in "XYZ".chars() {
+            buffer.replace_char(ch);
+        }
+        assert_eq!(buffer.file_text.to_string(), "aXYZ\n");
+        buffer.replace_backspace();
+        buffer.replace_backspace();
+        assert_eq!(buffer.file_text.to_string(), "aXc\n");
+        buffer.replace_backspace();
+        buffer.replace_backspace();
+        assert_eq!(buffer.file_text.to_string(), "abc\n");
+        assert_eq!(buffer.cursor_char_idx(), 0);
+    }
+}
@@ -0,0 +1,160 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use regex::Regex;
+use ropey::RopeSlice;
+
+use super::core::Buffer;
+
+/// Char ranges of the matches of `regex` on a line, from the start of the line. The line is
+/// read straight from the rope chunk holding it when there is one, which saves copying every
+/// line of a large file.
+fn line_matches(regex: &Regex, line: RopeSlice) -> Vec<Range<usize>> {
+    let text = match line.as_str() {
+        Some(text) => Cow::Borrowed(text),
+        None => Cow::Owned(line.to_string()),
+    };
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+    regex
+        .find_iter(text)
+        .map(|found| {
+            let start = text[..found.start()].chars().count();
+            start..start + found.as_str().chars().count()
+        })
+        .collect()
+}
+
+impl Buffer {
+    /// Char ranges of every match of `regex` on the given lines. Matches don't span lines.
+    pub fn search_matches(&self, regex: &Regex, lines: Range<usize>) -> Vec<Range<usize>> {
+        let lines = lines.start..lines.end.min(self.file_text.len_lines());
+        if lines.is_empty() {
+            return Vec::new();
+        }
+        let mut line_start = self.file_text.line_to_char(lines.start);
+        let mut matches = Vec::new();
+        for line in self.file_text.lines_at(lines.start).take(lines.len()) {
+            matches.extend(
+                line_matches(regex, line)
+                    .into_iter()
+                    .map(|found| line_start + found.start..line_start + found.end),
+            );
+            line_start += line.len_chars();
+        }
+        matches
+    }
+
+    /// Start of the first match after `char_idx` (or before it when going backwards),
+    /// wrapping around the ends of the file. Lines are walked from the cursor one and the
+    /// search stops at the first line with a match.
+    pub fn find_match(&self, regex: &Regex, char_idx: usize, forward: bool) -> Option<usize> {
+        let char_idx = char_idx.min(self.file_text.len_chars());
+        let cursor_line = self.file_text.char_to_line(char_idx);
+        let cursor_line_start = self.file_text.line_to_char(cursor_line);
+        let mut on_cursor_line = line_matches(regex, self.file_text.line(cursor_line))
+            .into_iter()
+            .map(|found| cursor_line_start + found.start);
+
+        if forward {
+            if let Some(found) = on_cursor_line.find(|start| *start > char_idx) {
+                return Some(found);
+            }
+            // The lines below the cursor, then from the top of the file back to the cursor
+            // line, for matches before the cursor.
+            let below = self.file_text.lines_at(cursor_line + 1);
+            let mut line_start = cursor_line_start + self.file_text.line(cursor_line).len_chars();
+            for line in below {
+                if let Some(found) = line_matches(regex, line).first() {
+                    return Some(line_start + found.start);
+                }
+                line_start += line.len_chars();
+            }
+            let mut line_start = 0;
+            for line in self.file_text.lines().take(cursor_line + 1) {
+                if let Some(found) = line_matches(regex, line).first() {
+                    return Some(line_start + found.start);
+                }
+                line_start += line.len_chars();
+            }
+        } else {
+            if let Some(found) = on_cursor_line.rev().find(|start| *start < char_idx) {
+                return Some(found);
+            }
+            // The lines above the cursor, then from the bottom of the file back to the cursor
+            // line, for matches after the cursor.
+            let mut above = self.file_text.lines_at(cursor_line);
+            let mut line_start = cursor_line_start;
+            while let Some(line) = above.prev() {
+                line_start -= line.len_chars();
+                if let Some(found) = line_matches(regex, line).last() {
+                    return Some(line_start + found.start);
+                }
+            }
+            let mut from_bottom = self.file_text.lines_at(self.file_text.len_lines());
+            let mut line_start = self.file_text.len_chars();
+            for _ in cursor_line..self.file_text.len_lines() {
+                let line = from_bottom.prev()?;
+                line_start -= line.len_chars();
+                if let Some(found) = line_matches(regex, line).last() {
+                    return Some(line_start + found.start);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::*;
+
+    #[test]
+    fn test_find_match_wraps() {
+        let buffer = Buffer::new(None, Rope::from_str("foo bar\nbaz foo\n"), 80, 24);
+        let regex = Regex::new("foo").unwrap();
+
+        assert_eq!(buffer.search_matches(&regex, 0..3), vec![0..3, 12..15]);
+        assert_eq!(buffer.find_match(&regex, 0, true), Some(12));
+        assert_eq!(buffer.find_match(&regex, 12, true), Some(0));
+        assert_eq!(buffer.find_match(&regex, 12, false), Some(0));
+        assert_eq!(buffer.find_match(&regex, 0, false), Some(12));
+        assert_eq!(buffer.find_match(&regex, 5, false), Some(0));
+    }
+
+    #[test]
+    fn test_find_match_across_chunks() {
+        // Lines longer than a rope chunk aren't stored in one piece.
+        let long_line = "x".repeat(5000);
+        let text = format!("foo\n{long_line}foo\n{}bar\n", "line\n".repeat(1000));
+        let buffer = Buffer::new(None, Rope::from_str(&text), 80, 24);
+        let second = 4 + 5000;
+        let bar = text.find("bar").unwrap();
+
+        assert_eq!(
+            buffer.find_match(&Regex::new("foo").unwrap(), 0, true),
+            Some(second)
+        );
+        assert_eq!(
+            buffer.find_match(&Regex::new("foo").unwrap(), bar, false),
+            Some(second)
+        );
+        assert_eq!(
+            buffer.find_match(&Regex::new("bar").unwrap(), 0, false),
+            Some(bar)
+        );
+        assert_eq!(
+            buffer.find_match(&Regex::new("^foo$").unwrap(), bar, true),
+            Some(0)
+        );
+        assert_eq!(
+            buffer.find_match(&Regex::new("baz").unwrap(), bar, false),
+            None
+        );
+        assert_eq!(
+            buffer.search_matches(&Regex::new("o+").unwrap(), 1..2),
+            vec![second + 1..second + 3]
+        );
+    }
+}
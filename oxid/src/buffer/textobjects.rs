@@ -0,0 +1,262 @@
+use std::ops::Range;
+
+use super::core::Buffer;
+use super::types::TextObject;
+
+impl Buffer {
+    /// Char range covered by the given text object around the cursor, if there is one.
+    pub fn text_object_range(&self, object: TextObject) -> Option<Range<usize>> {
+        match object {
+            TextObject::Word { around } => self.word_object(around, false),
+            TextObject::BigWord { around } => self.word_object(around, true),
+            TextObject::Brackets {
+                open,
+                close,
+                around,
+            } => self.bracket_object(open, close, around),
+            TextObject::Quotes { quote, around } => self.quote_object(quote, around),
+            TextObject::Paragraph { around } => self.paragraph_object(around),
+            TextObject::Tag { around } => self.tag_object(around),
+        }
+    }
+
+    fn word_object(&self, around: bool, big: bool) -> Option<Range<usize>> {
+        let line_idx = self.current_position.line;
+        let mut chars = self.get_line_chars(line_idx)?;
+        // The line break is never part of a word object.
+        if chars.last() == Some(&'\n') {
+            chars.pop();
+        }
+        if chars.is_empty() {
+            return None;
+        }
+        let col = self
+            .current_position
+            .character
+            .saturating_sub(self.numbar_space)
+            .min(chars.len() - 1);
+
+        let (mut start, mut end) = if chars[col].is_whitespace() {
+            let start = chars[..col]
+                .iter()
+                .rposition(|c| !c.is_whitespace())
+                .map_or(0, |pos| pos + 1);
+            let end = self.skip_whitespace_forward(&chars, col);
+            (start, end)
+        } else if big {
+            let start = chars[..col]
+                .iter()
+                .rposition(|c| c.is_whitespace())
+                .map_or(0, |pos| pos + 1);
+            let end = chars[col..]
+                .iter()
+                .position(|c| c.is_whitespace())
+                .map_or(chars.len(), |pos| col + pos);
+            (start, end)
+        } else {
+            let start = self.find_word_start(&chars, col)?;
+            let end = self.find_word_end(&chars, col)? + 1;
+            (start, end)
+        };
+
+        if around {
+            if chars[col].is_whitespace() {
+                // On whitespace "aw" takes the whitespace plus the following word.
+                while end < chars.len() && !chars[end].is_whitespace() {
+                    end += 1;
+                }
+            } else if end < chars.len() && chars[end].is_whitespace() {
+                end = self.skip_whitespace_forward(&chars, end);
+            } else {
+                while start > 0 && chars[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+            }
+        }
+
+        let line_start = self.file_text.line_to_char(line_idx);
+        Some(line_start + start..line_start + end)
+    }
+
+    fn bracket_object(&self, open: char, close: char, around: bool) -> Option<Range<usize>> {
+        let text = &self.file_text;
+        let cursor = self.cursor_char_idx();
+        if text.len_chars() == 0 {
+            return None;
+        }
+
+        // Find the opening bracket enclosing the cursor. When the cursor is on a closing
+        // bracket, scanning backwards from the previous char finds its own opening one.
+        let open_idx = if cursor < text.len_chars() && text.char(cursor) == open {
+            cursor
+        } else {
+            let mut depth = 0;
+            let mut idx = cursor;
+            loop {
+                if idx == 0 {
+                    return None;
+                }
+                idx -= 1;
+                let ch = text.char(idx);
+                if ch == close {
+                    depth += 1;
+                } else if ch == open {
+                    if depth == 0 {
+                        break idx;
+                    }
+                    depth -= 1;
+                }
+            }
+        };
+
+        // And the closing bracket matching it.
+        let mut depth = 0;
+        let mut close_idx = None;
+        for (idx, ch) in text.chars_at(open_idx + 1).enumerate() {
+            if ch == open {
+                depth += 1;
+            } else if ch == close {
+                if depth == 0 {
+                    close_idx = Some(open_idx + 1 + idx);
+                    break;
+                }
+                depth -= 1;
+            }
+        }
+        let close_idx = close_idx?;
+
+        if around {
+            return Some(open_idx..close_idx + 1);
+        }
+
+        // For a block spanning several lines, like vim, keep the brackets on their own lines.
+        let mut start = open_idx + 1;
+        let mut end = close_idx;
+        if text.char(start) == '\n' {
+            start += 1;
+            let close_line_start = text.line_to_char(text.char_to_line(close_idx));
+            if text
+                .slice(close_line_start..close_idx)
+                .chars()
+                .all(|c| c.is_whitespace())
+            {
+                end = close_line_start;
+            }
+        }
+        Some(start.min(end)..end)
+    }
+
+    fn quote_object(&self, quote: char, around: bool) -> Option<Range<usize>> {
+        let line_idx = self.current_position.line;
+        let chars = self.get_line_chars(line_idx)?;
+        let col = self
+            .current_position
+            .character
+            .saturating_sub(self.numbar_space);
+
+        let quotes: Vec<usize> = chars
+            .iter()
+            .enumerate()
+            .filter(|(idx, ch)| **ch == quote && (*idx == 0 || chars[idx - 1] != '\\'))
+            .map(|(idx, _)| idx)
+            .collect();
+
+        // Quotes pair up from the start of the line, use the pair around the cursor or
+        // the first one after it.
+        let (start, end) = quotes
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .find(|(start, end)| (*start <= col && col <= *end) || col < *start)?;
+
+        let line_start = self.file_text.line_to_char(line_idx);
+        if around {
+            let mut end = end + 1;
+            while end < chars.len() && chars[end] != '\n' && chars[end].is_whitespace() {
+                end += 1;
+            }
+            Some(line_start + start..line_start + end)
+        } else {
+            Some(line_start + start + 1..line_start + end)
+        }
+    }
+
+    fn paragraph_object(&self, around: bool) -> Option<Range<usize>> {
+        let is_blank = |line: usize| self.file_text.line(line).chars().all(|c| c.is_whitespace());
+        let last_line = self.file_text.len_lines() - 1;
+        let line = self.current_position.line;
+        let blank = is_blank(line);
+
+        let mut first = line;
+        while first > 0 && is_blank(first - 1) == blank {
+            first -= 1;
+        }
+        let mut last = line;
+        while last < last_line && is_blank(last + 1) == blank {
+            last += 1;
+        }
+
+        if around {
+            if last < last_line {
+                last += 1;
+                while last < last_line && is_blank(last + 1) != blank {
+                    last += 1;
+                }
+            } else {
+                while first > 0 && is_blank(first - 1) != blank {
+                    first -= 1;
+                }
+            }
+        }
+
+        Some(self.lines_char_range(first, last))
+    }
+
+    fn tag_object(&self, around: bool) -> Option<Range<usize>> {
+        let chars: Vec<char> = self.file_text.chars().collect();
+        let cursor = self.cursor_char_idx();
+
+        // Every matched pair of tags as (open start, open end, close start, close end).
+        let mut pairs: Vec<(usize, usize, usize, usize)> = Vec::new();
+        let mut open_tags: Vec<(String, usize, usize)> = Vec::new();
+        let mut idx = 0;
+        while idx < chars.len() {
+            if chars[idx] != '<' {
+                idx += 1;
+                continue;
+            }
+            let Some(len) = chars[idx..].iter().position(|c| *c == '>') else {
+                break;
+            };
+            let tag_end = idx + len + 1;
+            let tag: String = chars[idx + 1..tag_end - 1].iter().collect();
+
+            if let Some(name) = tag.strip_prefix('/') {
+                let name = name.trim().to_string();
+                if let Some(pos) = open_tags.iter().rposition(|(open, _, _)| *open == name) {
+                    let (_, open_start, open_end) = open_tags[pos];
+                    pairs.push((open_start, open_end, idx, tag_end));
+                    open_tags.truncate(pos);
+                }
+            } else if !tag.ends_with('/') && !tag.starts_with('!') && !tag.starts_with('?') {
+                let name = tag
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+                open_tags.push((name, idx, tag_end));
+            }
+            idx = tag_end;
+        }
+
+        let (open_start, open_end, close_start, close_end) = pairs
+            .into_iter()
+            .filter(|(open_start, _, _, close_end)| *open_start <= cursor && cursor < *close_end)
+            .min_by_key(|(open_start, _, _, close_end)| close_end - open_start)?;
+
+        if around {
+            Some(open_start..close_end)
+        } else {
+            Some(open_end..close_start)
+        }
+    }
+}
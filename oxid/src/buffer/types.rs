@@ -17,14 +17,262 @@ impl CharType {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BufferPosition {
     pub line: usize,
     pub character: usize,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionKind {
+    /// From start up to (not including) end.
+    #[default]
+    Char,
+    /// Whole lines between start and end.
+    Line,
+    /// The rectangle between start and end, both columns included.
+    Block,
+}
+
 #[derive(Debug, Clone)]
 pub struct Selection {
     pub start: BufferPosition,
     pub end: BufferPosition,
+    pub kind: SelectionKind,
+}
+
+impl Selection {
+    /// Returns (first line, last line, first column, last column) of the selection,
+    /// columns not counting the number bar.
+    pub fn bounds(&self, numbar_space: usize) -> (usize, usize, usize, usize) {
+        let start_col = self.start.character.saturating_sub(numbar_space);
+        let end_col = self.end.character.saturating_sub(numbar_space);
+        (
+            self.start.line.min(self.end.line),
+            self.start.line.max(self.end.line),
+            start_col.min(end_col),
+            start_col.max(end_col),
+        )
+    }
+}
+
+/// A block insert ("I" / "A" on a visual block) in progress. What gets typed on the
+/// first line is copied to the other lines when leaving Insert mode.
+#[derive(Debug, Clone)]
+pub struct BlockInsert {
+    pub first_line: usize,
+    pub last_line: usize,
+    pub column: usize,
+    /// Length of the first line when the insert started.
+    pub line_len: usize,
+    pub len_lines: usize,
+    /// Short lines are padded with spaces when appending, and skipped when inserting.
+    pub append: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Down,
+    Up,
+    Right,
+    DisplayDown, // "gj", a display row down when soft-wrapping
+    DisplayUp,   // "gk"
+    NextWord,
+    PreviousWord,
+    EndOfWord,
+    LineStart,
+    LineEnd,
+    FirstNonBlank,                // "^"
+    FindChar(CharSearch),         // "fx", "tx", "Fx", "Tx"
+    RepeatFind { reverse: bool }, // ";" and ","
+    ParagraphBackward,            // "{"
+    ParagraphForward,             // "}"
+    MatchingBracket,              // "%"
+    FileStart,                    // "gg"
+    FileEnd,                      // "G"
+    ScreenTop,                    // "H"
+    ScreenMiddle,                 // "M"
+    ScreenBottom,                 // "L"
+}
+
+/// Case conversion of the "gu", "gU" and "g~" operators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    Lower,
+    Upper,
+    Toggle,
+}
+
+impl Case {
+    pub fn convert(self, text: &str) -> String {
+        match self {
+            Self::Lower => text.to_lowercase(),
+            Self::Upper => text.to_uppercase(),
+            Self::Toggle => text
+                .chars()
+                .map(|c| {
+                    if c.is_uppercase() {
+                        c.to_lowercase().to_string()
+                    } else {
+                        c.to_uppercase().to_string()
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A search for a char on the cursor line, made by "f", "t", "F" or "T".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharSearch {
+    pub ch: char,
+    pub forward: bool,
+    /// "t" and "T" stop right before the char.
+    pub till: bool,
+}
+
+impl CharSearch {
+    /// The search of "f", "t", "F" or "T".
+    pub fn new(key: char, ch: char) -> Option<Self> {
+        let (forward, till) = match key {
+            'f' => (true, false),
+            't' => (true, true),
+            'F' => (false, false),
+            'T' => (false, true),
+            _ => return None,
+        };
+        Some(CharSearch { ch, forward, till })
+    }
+}
+
+impl Motion {
+    pub fn from_char(ch: char) -> Option<Self> {
+        match ch {
+            'h' => Some(Self::Left),
+            'j' => Some(Self::Down),
+            'k' => Some(Self::Up),
+            'l' => Some(Self::Right),
+            'w' => Some(Self::NextWord),
+            'b' => Some(Self::PreviousWord),
+            'e' => Some(Self::EndOfWord),
+            '0' => Some(Self::LineStart),
+            '$' => Some(Self::LineEnd),
+            '^' => Some(Self::FirstNonBlank),
+            '{' => Some(Self::ParagraphBackward),
+            '}' => Some(Self::ParagraphForward),
+            '%' => Some(Self::MatchingBracket),
+            'G' => Some(Self::FileEnd),
+            'H' => Some(Self::ScreenTop),
+            'M' => Some(Self::ScreenMiddle),
+            'L' => Some(Self::ScreenBottom),
+            ';' => Some(Self::RepeatFind { reverse: false }),
+            ',' => Some(Self::RepeatFind { reverse: true }),
+            _ => None,
+        }
+    }
+
+    /// Linewise motions make operators act on whole lines.
+    pub fn is_linewise(&self) -> bool {
+        matches!(
+            self,
+            Self::Down
+                | Self::Up
+                | Self::FileStart
+                | Self::FileEnd
+                | Self::ScreenTop
+                | Self::ScreenMiddle
+                | Self::ScreenBottom
+        )
+    }
+
+    /// Inclusive motions make operators also act on the character they land on.
+    pub fn is_inclusive(&self) -> bool {
+        matches!(self, Self::EndOfWord | Self::MatchingBracket)
+    }
+
+    /// Motions that go far enough to be remembered in the jumplist.
+    pub fn is_jump(&self) -> bool {
+        matches!(
+            self,
+            Self::ParagraphBackward
+                | Self::ParagraphForward
+                | Self::MatchingBracket
+                | Self::FileStart
+                | Self::FileEnd
+                | Self::ScreenTop
+                | Self::ScreenMiddle
+                | Self::ScreenBottom
+        )
+    }
+
+    /// Char searches are inclusive when they go forward, and don't operate at all when the
+    /// char is not found.
+    pub fn is_char_search(&self) -> bool {
+        matches!(self, Self::FindChar(_) | Self::RepeatFind { .. })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextObject {
+    Word {
+        around: bool,
+    }, // "iw", "aw"
+    BigWord {
+        around: bool,
+    }, // "iW", "aW"
+    Brackets {
+        open: char,
+        close: char,
+        around: bool,
+    }, // "i(", "a{", ...
+    Quotes {
+        quote: char,
+        around: bool,
+    }, // "i\"", "a'", ...
+    Paragraph {
+        around: bool,
+    }, // "ip", "ap"
+    Tag {
+        around: bool,
+    }, // "it", "at"
+}
+
+impl TextObject {
+    /// Builds a text object from its "i"/"a" prefix and the character naming it.
+    pub fn from_chars(prefix: char, ch: char) -> Option<Self> {
+        let around = match prefix {
+            'i' => false,
+            'a' => true,
+            _ => return None,
+        };
+        match ch {
+            'w' => Some(Self::Word { around }),
+            'W' => Some(Self::BigWord { around }),
+            '(' | ')' | 'b' => Some(Self::Brackets {
+                open: '(',
+                close: ')',
+                around,
+            }),
+            '[' | ']' => Some(Self::Brackets {
+                open: '[',
+                close: ']',
+                around,
+            }),
+            '{' | '}' | 'B' => Some(Self::Brackets {
+                open: '{',
+                close: '}',
+                around,
+            }),
+            '<' | '>' => Some(Self::Brackets {
+                open: '<',
+                close: '>',
+                around,
+            }),
+            '"' | '\'' | '`' => Some(Self::Quotes { quote: ch, around }),
+            'p' => Some(Self::Paragraph { around }),
+            't' => Some(Self::Tag { around }),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,155 @@
+use std::path::{Path, PathBuf};
+
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+
+use super::core::Buffer;
+use super::history::History;
+
+/// Undo history saved to disk, along with a hash of the text it leads to, so that it's only
+/// restored on that same text.
+#[derive(Serialize, Deserialize)]
+struct UndoFile {
+    hash: String,
+    history: History,
+}
+
+/// FNV-1a hash of the text. Unlike `DefaultHasher`, it's the same across runs and Rust
+/// versions.
+fn text_hash(text: &Rope) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in text.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{hash:016x}")
+}
+
+/// Undo file of a file, in `$XDG_STATE_HOME/oxid/undo` (`~/.local/state/oxid/undo` by
+/// default) and named after its canonical path, with "%" for the path separators.
+fn undo_file_path(file_path: &str) -> Option<PathBuf> {
+    let state_dir = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            #[allow(deprecated)]
+            let home = std::env::home_dir()?;
+            home.join(".local").join("state")
+        }
+    };
+    let canonical_path =
+        std::fs::canonicalize(file_path).unwrap_or_else(|_| PathBuf::from(file_path));
+    let name = canonical_path.to_string_lossy().replace(['/', '\\'], "%");
+    Some(state_dir.join("oxid").join("undo").join(name))
+}
+
+impl Buffer {
+    /// Saves the undo history to the undo file of the buffer, when it's saved or closed.
+    /// Buffers without changes and large files have none.
+    pub fn save_undo_file(&self) -> anyhow::Result<()> {
+        match self.file_path.as_deref().and_then(undo_file_path) {
+            Some(path) => self.write_undo_file(&path),
+            None => Ok(()),
+        }
+    }
+
+    /// Restores the undo history saved for the file, unless the file changed since.
+    pub fn load_undo_file(&mut self) {
+        if self.large_file {
+            return;
+        }
+        self.mark_saved();
+        if let Some(path) = self.file_path.as_deref().and_then(undo_file_path) {
+            _ = self.read_undo_file(&path);
+        }
+    }
+
+    /// Records that the text of the buffer is the one on disk, after loading or saving it.
+    pub fn mark_saved(&mut self) {
+        if !self.large_file {
+            self.saved_hash = Some(text_hash(&self.file_text));
+        }
+    }
+
+    fn write_undo_file(&self, path: &Path) -> anyhow::Result<()> {
+        if self.large_file || (self.history.revisions.len() == 1 && !self.history.has_pending()) {
+            return Ok(());
+        }
+        // With unsaved changes, keep the undo file written by the last save: the history
+        // has to lead to the text on disk to be restored.
+        let hash = text_hash(&self.file_text);
+        if self.saved_hash.as_ref() != Some(&hash) {
+            return Ok(());
+        }
+        // Changes still being made (e.g. in Insert mode) are saved as a revision.
+        let mut history = self.history.clone();
+        history.commit(self.cursor_char_idx());
+        let undo_file = UndoFile { hash, history };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, toml::to_string(&undo_file)?)?;
+        Ok(())
+    }
+
+    /// Returns true if the history was restored.
+    fn read_undo_file(&mut self, path: &Path) -> anyhow::Result<bool> {
+        let undo_file: UndoFile = toml::from_str(&std::fs::read_to_string(path)?)?;
+        if self.saved_hash.as_ref() != Some(&undo_file.hash) {
+            return Ok(false);
+        }
+        self.history = undo_file.history;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_file_round_trip() {
+        let path = std::env::temp_dir().join("oxid_test_undo_file");
+        let mut buffer = Buffer::new(None, Rope::from_str("one\n"), 80, 24);
+        buffer.insert_text(3, " two");
+        buffer.commit_history();
+        buffer.insert_text(7, " three");
+        buffer.mark_saved();
+        buffer.write_undo_file(&path).unwrap();
+
+        let mut reopened = Buffer::new(None, Rope::from_str("one two three\n"), 80, 24);
+        reopened.mark_saved();
+        assert!(reopened.read_undo_file(&path).unwrap());
+        assert!(reopened.undo());
+        assert_eq!(reopened.file_text.to_string(), "one two\n");
+        assert!(reopened.undo());
+        assert_eq!(reopened.file_text.to_string(), "one\n");
+
+        let mut changed = Buffer::new(None, Rope::from_str("one two four\n"), 80, 24);
+        changed.mark_saved();
+        assert!(!changed.read_undo_file(&path).unwrap());
+        assert!(!changed.undo());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_undo_file_with_unsaved_changes() {
+        let path = std::env::temp_dir().join("oxid_test_undo_file_unsaved");
+        let mut buffer = Buffer::new(None, Rope::from_str("one\n"), 80, 24);
+        buffer.mark_saved();
+        buffer.insert_text(3, " two");
+        buffer.commit_history();
+        // Saved with ":w", then changed again and closed without saving.
+        buffer.mark_saved();
+        buffer.write_undo_file(&path).unwrap();
+        buffer.insert_text(7, " three");
+        buffer.commit_history();
+        buffer.write_undo_file(&path).unwrap();
+
+        let mut reopened = Buffer::new(None, Rope::from_str("one two\n"), 80, 24);
+        reopened.mark_saved();
+        assert!(reopened.read_undo_file(&path).unwrap());
+        assert!(reopened.undo());
+        assert_eq!(reopened.file_text.to_string(), "one\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
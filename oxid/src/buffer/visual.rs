@@ -1,10 +1,63 @@
+use std::ops::Range;
+
 use super::core::Buffer;
+use super::types::{BlockInsert, BufferPosition, Selection, SelectionKind};
 
 impl Buffer {
+    /// Char ranges of every row of a block selection, clamped to each line's content.
+    pub fn block_ranges(&self) -> Vec<Range<usize>> {
+        let Some(selection) = &self.selection else {
+            return Vec::new();
+        };
+        let (first, last, _, _) = selection.bounds(self.numbar_space);
+        (first..=last.min(self.file_text.len_lines() - 1))
+            .map(|line| {
+                let line_start = self.file_text.line_to_char(line);
+                let columns = self.block_columns(selection, line);
+                line_start + columns.start..line_start + columns.end
+            })
+            .collect()
+    }
+
+    /// Columns of `line` inside a block selection, clamped to the line's content. A block
+    /// is a rectangle on screen: it goes from the left cell of its leftmost corner to the
+    /// right cell of its rightmost one, so on lines with tabs or wide chars it takes fewer
+    /// chars.
+    pub fn block_columns(&self, selection: &Selection, line: usize) -> Range<usize> {
+        // Display columns taken by the grapheme at a corner.
+        let cells = |position: &BufferPosition| {
+            let column = position.character.saturating_sub(self.numbar_space);
+            let start = self.display_column(position.line, column);
+            let end = self.display_column(position.line, self.next_grapheme(position.line, column));
+            start..end.max(start + 1)
+        };
+        let (start_cells, end_cells) = (cells(&selection.start), cells(&selection.end));
+        let left = start_cells.start.min(end_cells.start);
+        let right = start_cells.end.max(end_cells.end) - 1;
+
+        let content_len = self.line_content_len(line);
+        let start = self.column_at_display(line, left).min(content_len);
+        let last = self.column_at_display(line, right);
+        let end = self.next_grapheme(line, last).min(content_len);
+        start..end.max(start)
+    }
+
     pub fn update_selected_string(&mut self) {
         if let Some(selection) = &self.selection {
+            self.last_selection = Some(selection.clone());
+            if selection.kind == SelectionKind::Line {
+                let (first, last, _, _) = selection.bounds(self.numbar_space);
+                self.selected_string = Some(self.lines_text(first, last));
+            } else if selection.kind == SelectionKind::Block {
+                let rows: Vec<String> = self
+                    .block_ranges()
+                    .into_iter()
+                    .map(|range| self.file_text.slice(range).to_string())
+                    .collect();
+                self.selected_string = Some(rows.join("\n"));
+            }
             // Single line selection
-            if selection.start.line == selection.end.line {
+            else if selection.start.line == selection.end.line {
                 let start = selection.start.character - self.numbar_space;
                 let end = selection.end.character - self.numbar_space;
                 // Normalize so that we don't get indexing errors if selection went backwards.
@@ -89,4 +142,68 @@ impl Buffer {
             self.selected_string = None;
         }
     }
+
+    /// Copies the text typed on the first line of a block insert to the rest of its lines.
+    pub fn finish_block_insert(&mut self, block: &BlockInsert) -> bool {
+        if self.file_text.len_lines() != block.len_lines
+            || self.current_position.line != block.first_line
+        {
+            return false;
+        }
+        let inserted_len = self
+            .line_content_len(block.first_line)
+            .saturating_sub(block.line_len);
+        if inserted_len == 0 {
+            return false;
+        }
+        let first_start = self.file_text.line_to_char(block.first_line) + block.column;
+        let text = self
+            .file_text
+            .slice(first_start..first_start + inserted_len)
+            .to_string();
+
+        let last_line = block.last_line.min(self.file_text.len_lines() - 1);
+        for line in block.first_line + 1..=last_line {
+            let content_len = self.line_content_len(line);
+            let line_start = self.file_text.line_to_char(line);
+            if content_len < block.column {
+                if !block.append {
+                    continue;
+                }
+                let padding = " ".repeat(block.column - content_len);
+                self.insert_text(line_start + content_len, &padding);
+            }
+            self.insert_text(line_start + block.column, &text);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::*;
+
+    #[test]
+    fn test_block_with_tab() {
+        let mut buffer = Buffer::new(None, Rope::from_str("a\tb\nabcdef\n"), 80, 24);
+        // From "c" on the second line up to "b", which is drawn at column 4 after the tab.
+        buffer.selection = Some(Selection {
+            start: BufferPosition {
+                line: 1,
+                character: 2 + buffer.numbar_space,
+            },
+            end: BufferPosition {
+                line: 0,
+                character: 2 + buffer.numbar_space,
+            },
+            kind: SelectionKind::Block,
+        });
+        let selection = buffer.selection.clone().unwrap();
+        assert_eq!(buffer.block_columns(&selection, 0), 1..3);
+        assert_eq!(buffer.block_columns(&selection, 1), 2..5);
+        buffer.update_selected_string();
+        assert_eq!(buffer.selected_string.as_deref(), Some("\tb\ncde"));
+    }
 }
@@ -1,5 +1,10 @@
 use std::str::FromStr;
 
+use regex::{Regex, RegexBuilder};
+
+use crate::encoding::{FileEncoding, LineEnding};
+
+#[derive(Debug, PartialEq, Eq)]
 pub enum Command {
     SaveAll,     // ":wa"
     QuitAll,     // ":qa"
@@ -16,6 +21,232 @@ pub enum Command {
 
     StartLsp(String),
     StopLsp,
+
+    NoHighlight, // ":noh"
+
+    Substitute(Substitute), // ":s/pat/rep/flags", ":%s/...", ":'<,'>s/..."
+
+    Set(SetOption), // ":set wrap"
+}
+
+/// A buffer setting changed with ":set".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SetOption {
+    Wrap(bool),                 // "wrap", "nowrap"
+    FileFormat(LineEnding),     // "fileformat=dos", "ff=unix"
+    FileEncoding(FileEncoding), // "fileencoding=latin1", "fenc=utf-8"
+    TabStop(usize),             // "tabstop=8", "ts=8"
+    ShiftWidth(usize),          // "shiftwidth=2", "sw=2"
+    ExpandTab(bool),            // "expandtab", "noexpandtab", "et", "noet"
+}
+
+impl FromStr for SetOption {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some(("fileformat" | "ff", value)) => Ok(Self::FileFormat(value.parse()?)),
+            Some(("fileencoding" | "fenc", value)) => Ok(Self::FileEncoding(value.parse()?)),
+            Some(("tabstop" | "ts", value)) => Ok(Self::TabStop(parse_width(value)?)),
+            Some(("shiftwidth" | "sw", value)) => Ok(Self::ShiftWidth(parse_width(value)?)),
+            Some(_) => anyhow::bail!("Unknown option: {s}"),
+            None => match s {
+                "wrap" => Ok(Self::Wrap(true)),
+                "nowrap" => Ok(Self::Wrap(false)),
+                "expandtab" | "et" => Ok(Self::ExpandTab(true)),
+                "noexpandtab" | "noet" => Ok(Self::ExpandTab(false)),
+                _ => anyhow::bail!("Unknown option: {s}"),
+            },
+        }
+    }
+}
+
+/// A width in columns, for "tabstop" and "shiftwidth".
+fn parse_width(value: &str) -> anyhow::Result<usize> {
+    match value.parse::<usize>() {
+        Ok(width) if width > 0 => Ok(width),
+        _ => anyhow::bail!("Invalid width: {value}"),
+    }
+}
+
+/// Line an Ex range starts or ends at, resolved when the command runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Address {
+    Line(usize), // "12", counted from 1
+    Current,     // "."
+    Last,        // "$"
+    Mark(char),  // "'a", "'<"
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineAddress {
+    pub address: Address,
+    pub offset: isize, // "+2", "-1"
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineRange {
+    Whole, // "%"
+    Lines(LineAddress, LineAddress),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitute {
+    pub range: LineRange,
+    /// Empty to use the last search pattern.
+    pub pattern: String,
+    /// Replacement in the syntax of the regex crate ("$1" instead of vim's "\1").
+    pub replacement: String,
+    pub global: bool,      // "g", every match of a line instead of the first one
+    pub ignore_case: bool, // "i"
+    pub confirm: bool,     // "c", ask before each replacement
+}
+
+impl Substitute {
+    pub fn regex(&self, last_search: Option<&str>) -> anyhow::Result<Regex> {
+        let pattern = match (self.pattern.is_empty(), last_search) {
+            (false, _) => self.pattern.as_str(),
+            (true, Some(last)) => last,
+            (true, None) => anyhow::bail!("No previous search pattern"),
+        };
+        Ok(RegexBuilder::new(pattern)
+            .case_insensitive(self.ignore_case)
+            .build()?)
+    }
+
+    /// Parses what comes after the range, e.g. "s/foo/bar/g".
+    fn parse(range: LineRange, input: &str) -> anyhow::Result<Self> {
+        let mut chars = input.chars();
+        let delimiter = match chars.next() {
+            Some(ch) if !ch.is_alphanumeric() && !ch.is_whitespace() && ch != '\\' => ch,
+            _ => anyhow::bail!("Substitute needs a delimiter, like in :s/pat/rep/"),
+        };
+
+        // Split on the delimiter, unless it's escaped.
+        let mut parts = vec![String::new()];
+        let mut escaped = false;
+        for ch in chars {
+            if !escaped && ch == delimiter && parts.len() < 3 {
+                parts.push(String::new());
+                continue;
+            }
+            let part = parts.last_mut().expect("There is always a part");
+            if escaped {
+                if ch != delimiter {
+                    part.push('\\');
+                }
+                part.push(ch);
+                escaped = false;
+            } else if ch == '\\' {
+                escaped = true;
+            } else {
+                part.push(ch);
+            }
+        }
+        if escaped {
+            parts.last_mut().expect("There is always a part").push('\\');
+        }
+
+        let mut parts = parts.into_iter();
+        let pattern = parts.next().unwrap_or_default();
+        let replacement = vim_replacement(&parts.next().unwrap_or_default());
+        let flags = parts.next().unwrap_or_default();
+        if let Some(flag) = flags.chars().find(|c| !matches!(c, 'g' | 'i' | 'c')) {
+            anyhow::bail!("Unknown substitute flag: {flag}")
+        }
+
+        Ok(Self {
+            range,
+            pattern,
+            replacement,
+            global: flags.contains('g'),
+            ignore_case: flags.contains('i'),
+            confirm: flags.contains('c'),
+        })
+    }
+}
+
+/// Converts a vim replacement ("\1", "&", "\n") to the regex crate syntax ("${1}", "${0}").
+fn vim_replacement(input: &str) -> String {
+    let mut replacement = String::new();
+    let mut chars = input.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '$' => replacement.push_str("$$"),
+            '&' => replacement.push_str("${0}"),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => replacement.push_str(&format!("${{{digit}}}")),
+                Some('n') | Some('r') => replacement.push('\n'),
+                Some('t') => replacement.push('\t'),
+                Some('$') => replacement.push_str("$$"),
+                Some(other) => replacement.push(other),
+                None => replacement.push('\\'),
+            },
+            _ => replacement.push(ch),
+        }
+    }
+    replacement
+}
+
+/// Parses a single address like "12", ".", "$", "'a" or ".+2" from the start of `input`.
+fn parse_address(input: &str) -> (Option<LineAddress>, &str) {
+    let digits = input.chars().take_while(|c| c.is_ascii_digit()).count();
+    let (address, rest) = if digits > 0 {
+        let line = input[..digits].parse().unwrap_or(usize::MAX);
+        (Some(Address::Line(line)), &input[digits..])
+    } else if let Some(rest) = input.strip_prefix('.') {
+        (Some(Address::Current), rest)
+    } else if let Some(rest) = input.strip_prefix('$') {
+        (Some(Address::Last), rest)
+    } else if let Some(rest) = input.strip_prefix('\'')
+        && let Some(mark) = rest.chars().next()
+    {
+        (Some(Address::Mark(mark)), &rest[mark.len_utf8()..])
+    } else {
+        (None, input)
+    };
+
+    // An offset alone is relative to the current line, e.g. "+2".
+    let mut rest = rest;
+    let mut offset: isize = 0;
+    while let Some(sign) = rest.chars().next().filter(|c| *c == '+' || *c == '-') {
+        let digits = rest[1..].chars().take_while(|c| c.is_ascii_digit()).count();
+        let amount: isize = if digits == 0 {
+            1
+        } else {
+            rest[1..=digits].parse().unwrap_or(0)
+        };
+        offset += if sign == '+' { amount } else { -amount };
+        rest = &rest[1 + digits..];
+    }
+
+    match (address, offset) {
+        (None, 0) => (None, input),
+        (None, offset) => (
+            Some(LineAddress {
+                address: Address::Current,
+                offset,
+            }),
+            rest,
+        ),
+        (Some(address), offset) => (Some(LineAddress { address, offset }), rest),
+    }
+}
+
+/// Parses the range in front of a command, returns `None` when there is none.
+fn parse_range(input: &str) -> (Option<LineRange>, &str) {
+    if let Some(rest) = input.strip_prefix('%') {
+        return (Some(LineRange::Whole), rest);
+    }
+    let (Some(start), rest) = parse_address(input) else {
+        return (None, input);
+    };
+    if let Some(after_comma) = rest.strip_prefix(',')
+        && let (Some(end), rest) = parse_address(after_comma)
+    {
+        return (Some(LineRange::Lines(start, end)), rest);
+    }
+    (Some(LineRange::Lines(start, start)), rest)
 }
 
 impl Command {
@@ -28,6 +259,21 @@ impl FromStr for Command {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Substitute is parsed before splitting on whitespace, as its pattern may have some.
+        let (range, rest) = parse_range(s.trim_start());
+        if let Some(args) = rest
+            .strip_prefix("substitute")
+            .or_else(|| rest.strip_prefix('s'))
+            .filter(|args| args.starts_with(|c: char| !c.is_alphanumeric()))
+        {
+            let current = LineAddress {
+                address: Address::Current,
+                offset: 0,
+            };
+            let range = range.unwrap_or(LineRange::Lines(current, current));
+            return Ok(Self::Substitute(Substitute::parse(range, args)?));
+        }
+
         let mut cmd_parts = s.split_whitespace();
         if let Some(cmd) = cmd_parts.next() {
             match cmd {
@@ -76,6 +322,13 @@ impl FromStr for Command {
 
                 "LspStop" => Ok(Self::StopLsp),
 
+                "noh" | "nohlsearch" => Ok(Self::NoHighlight),
+
+                "set" | "se" => match (cmd_parts.next(), cmd_parts.next()) {
+                    (Some(option), None) => Ok(Self::Set(option.parse()?)),
+                    _ => anyhow::bail!(":set takes a single option"),
+                },
+
                 _ => anyhow::bail!("Unknown command: {cmd}"),
             }
         } else {
@@ -83,3 +336,96 @@ impl FromStr for Command {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(line: usize) -> LineAddress {
+        LineAddress {
+            address: Address::Line(line),
+            offset: 0,
+        }
+    }
+
+    #[test]
+    fn test_parse_substitute() {
+        let Command::Substitute(sub) = Command::parse(r"%s/(\w+) \/ x/\1 & \\/gi").unwrap() else {
+            panic!("Expected a substitute command");
+        };
+        assert_eq!(sub.range, LineRange::Whole);
+        assert_eq!(sub.pattern, r"(\w+) / x");
+        assert_eq!(sub.replacement, r"${1} ${0} \");
+        assert!(sub.global && sub.ignore_case && !sub.confirm);
+
+        let Command::Substitute(sub) = Command::parse("2,$s#a b#c#c").unwrap() else {
+            panic!("Expected a substitute command");
+        };
+        let last = LineAddress {
+            address: Address::Last,
+            offset: 0,
+        };
+        assert_eq!(sub.range, LineRange::Lines(line(2), last));
+        assert_eq!(
+            (sub.pattern.as_str(), sub.replacement.as_str()),
+            ("a b", "c")
+        );
+        assert!(sub.confirm);
+
+        assert!(Command::parse("s/a/b/x").is_err());
+        assert_eq!(Command::parse("12").unwrap(), Command::GoToLine(12));
+        assert!(Command::parse("sfoo").is_err());
+    }
+
+    #[test]
+    fn test_parse_set() {
+        assert_eq!(
+            Command::parse("set nowrap").unwrap(),
+            Command::Set(SetOption::Wrap(false))
+        );
+        assert_eq!(
+            Command::parse("set ff=dos").unwrap(),
+            Command::Set(SetOption::FileFormat(LineEnding::Dos))
+        );
+        assert_eq!(
+            Command::parse("se fileencoding=UTF-16LE").unwrap(),
+            Command::Set(SetOption::FileEncoding(FileEncoding::Utf16Le))
+        );
+        assert!(Command::parse("set ff=mac").is_err());
+        assert_eq!(
+            Command::parse("set ts=8").unwrap(),
+            Command::Set(SetOption::TabStop(8))
+        );
+        assert_eq!(
+            Command::parse("set noet").unwrap(),
+            Command::Set(SetOption::ExpandTab(false))
+        );
+        assert!(Command::parse("set sw=0").is_err());
+        assert!(Command::parse("set").is_err());
+        assert!(Command::parse("set foo").is_err());
+    }
+
+    #[test]
+    fn test_parse_range() {
+        let current = |offset| LineAddress {
+            address: Address::Current,
+            offset,
+        };
+        assert_eq!(parse_range("s/a/b/"), (None, "s/a/b/"));
+        assert_eq!(
+            parse_range(".,+3s"),
+            (Some(LineRange::Lines(current(0), current(3))), "s")
+        );
+        let marks = LineRange::Lines(
+            LineAddress {
+                address: Address::Mark('<'),
+                offset: 0,
+            },
+            LineAddress {
+                address: Address::Mark('>'),
+                offset: -1,
+            },
+        );
+        assert_eq!(parse_range("'<,'>-1s"), (Some(marks), "s"));
+    }
+}
@@ -1,8 +1,27 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Config {
     pub lsp: Vec<LspConfig>,
+    #[serde(default)]
+    pub filetype: Vec<FiletypeConfig>,
+    /// Files bigger than this many bytes open in large-file mode.
+    #[serde(default = "default_large_file_size")]
+    pub large_file_size: u64,
+}
+
+fn default_large_file_size() -> u64 {
+    50 * 1024 * 1024
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            lsp: Vec::new(),
+            filetype: Vec::new(),
+            large_file_size: default_large_file_size(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
@@ -11,6 +30,60 @@ pub struct LspConfig {
     pub command: String,
 }
 
+/// Settings for the files of a filetype. Like for `[[lsp]]`, the filetype is the extension
+/// of the file (e.g. "rs" or "py").
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(default)]
+pub struct FiletypeConfig {
+    pub filetype: String,
+    /// Columns a tab takes on screen.
+    pub tabstop: usize,
+    /// Columns of one indent level.
+    pub shiftwidth: usize,
+    /// Indent with spaces instead of tabs.
+    pub expandtab: bool,
+    /// Pairs closed automatically in Insert mode, written as the opening and the closing
+    /// chars (e.g. "()"). An empty list turns auto-pairing off.
+    pub pairs: Vec<String>,
+    /// Line comment token used by "gc" (e.g. "//"), instead of the built-in one.
+    pub comment: Option<String>,
+    /// Soft-wrap long lines at the window width instead of scrolling sideways.
+    pub wrap: bool,
+}
+
+impl Default for FiletypeConfig {
+    fn default() -> Self {
+        FiletypeConfig {
+            filetype: String::new(),
+            tabstop: 4,
+            shiftwidth: 4,
+            expandtab: true,
+            pairs: ["()", "[]", "{}", "\"\"", "''"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            comment: None,
+            wrap: false,
+        }
+    }
+}
+
+impl Config {
+    /// Whether a file is big enough to open in large-file mode.
+    pub fn is_large_file(&self, file_path: &str) -> bool {
+        std::fs::metadata(file_path).is_ok_and(|metadata| metadata.len() > self.large_file_size)
+    }
+
+    /// Settings for a filetype, the defaults if it has none in the config.
+    pub fn filetype_config(&self, filetype: Option<&str>) -> FiletypeConfig {
+        self.filetype
+            .iter()
+            .find(|config| Some(config.filetype.as_str()) == filetype)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
 pub fn read_config_file() -> anyhow::Result<Config> {
     #[allow(deprecated)]
     match std::env::home_dir() {
@@ -59,6 +132,11 @@ mod tests {
             [[lsp]]
             filetype = "gleam"
             command = "gleam lsp"
+
+            [[filetype]]
+            filetype = "go"
+            expandtab = false
+            pairs = ["()", "{}"]
         "#;
 
         let cfg_dir = config_dir();
@@ -87,9 +165,20 @@ mod tests {
                         filetype: "gleam".to_string(),
                         command: "gleam lsp".to_string()
                     }
-                ]
+                ],
+                filetype: vec![FiletypeConfig {
+                    filetype: "go".to_string(),
+                    tabstop: 4,
+                    shiftwidth: 4,
+                    expandtab: false,
+                    pairs: vec!["()".to_string(), "{}".to_string()],
+                    comment: None,
+                    wrap: false
+                }],
+                large_file_size: 50 * 1024 * 1024,
             }
         );
+        assert_eq!(cfg.filetype_config(Some("rs")), FiletypeConfig::default());
 
         fs::remove_file(&cfg_path).unwrap();
         fs::remove_dir_all(&cfg_dir).unwrap();
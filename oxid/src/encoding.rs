@@ -0,0 +1,437 @@
+use std::borrow::Cow;
+use std::fmt::Display;
+use std::str::FromStr;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileEncoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl FromStr for FileEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Self::Utf8),
+            "utf-16le" | "utf16le" => Ok(Self::Utf16Le),
+            "utf-16" | "utf16" | "utf-16be" | "utf16be" => Ok(Self::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Self::Latin1),
+            _ => anyhow::bail!("Unknown encoding: {s}"),
+        }
+    }
+}
+
+impl Display for FileEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Utf8 => write!(f, "utf-8"),
+            Self::Utf16Le => write!(f, "utf-16le"),
+            Self::Utf16Be => write!(f, "utf-16be"),
+            Self::Latin1 => write!(f, "latin1"),
+        }
+    }
+}
+
+impl FileEncoding {
+    /// Decodes the whole chars at the start of `bytes`, returns the text, how many bytes it
+    /// took and whether some of them weren't valid and were replaced. Unless `last`, a char
+    /// cut at the end is left for the next bytes.
+    fn decode_prefix(self, bytes: &[u8], last: bool) -> (String, usize, bool) {
+        match self {
+            Self::Utf8 => {
+                let end = if last {
+                    bytes.len()
+                } else {
+                    bytes.len() - incomplete_utf8_tail(bytes)
+                };
+                let text = String::from_utf8_lossy(&bytes[..end]);
+                let lossy = matches!(text, Cow::Owned(_));
+                (text.into_owned(), end, lossy)
+            }
+            Self::Utf16Le | Self::Utf16Be => {
+                let mut units: Vec<u16> = bytes
+                    .chunks_exact(2)
+                    .map(|b| match self {
+                        Self::Utf16Le => u16::from_le_bytes([b[0], b[1]]),
+                        _ => u16::from_be_bytes([b[0], b[1]]),
+                    })
+                    .collect();
+                // The first half of a surrogate pair waits for the second one.
+                if !last
+                    && units
+                        .last()
+                        .is_some_and(|unit| (0xD800..0xDC00).contains(unit))
+                {
+                    units.pop();
+                }
+                let end = if last { bytes.len() } else { units.len() * 2 };
+                let (text, invalid) = decode_utf16(units.into_iter());
+                // An odd byte left at the end of the file is dropped.
+                (text, end, invalid || end % 2 == 1)
+            }
+            Self::Latin1 => (
+                bytes.iter().map(|b| char::from(*b)).collect(),
+                bytes.len(),
+                false,
+            ),
+        }
+    }
+}
+
+/// Length of the UTF-8 char cut at the end of `bytes`, 0 if the last one is whole.
+fn incomplete_utf8_tail(bytes: &[u8]) -> usize {
+    for back in 1..=bytes.len().min(3) {
+        let byte = bytes[bytes.len() - back];
+        // Continuation bytes are 0b10xxxxxx, look for the byte starting the char.
+        if byte & 0xC0 != 0x80 {
+            let len = match byte {
+                0xF0.. => 4,
+                0xE0.. => 3,
+                0xC0.. => 2,
+                _ => 1,
+            };
+            return if len > back { back } else { 0 };
+        }
+    }
+    0
+}
+
+/// Line break written on save. Buffers always hold "\n" alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    #[default]
+    Unix, // "\n"
+    Dos, // "\r\n"
+}
+
+impl FromStr for LineEnding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unix" => Ok(Self::Unix),
+            "dos" => Ok(Self::Dos),
+            _ => anyhow::bail!("Unknown file format: {s}"),
+        }
+    }
+}
+
+impl LineEnding {
+    /// DOS when every line break of the text is "\r\n", Unix otherwise.
+    fn detect(text: &str) -> Self {
+        let line_breaks = text.matches('\n').count();
+        if line_breaks > 0 && text.matches("\r\n").count() == line_breaks {
+            Self::Dos
+        } else {
+            Self::Unix
+        }
+    }
+}
+
+impl Display for LineEnding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unix => write!(f, "unix"),
+            Self::Dos => write!(f, "dos"),
+        }
+    }
+}
+
+/// How a file is stored on disk, detected when loading it so that saving writes it back the
+/// same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FileFormat {
+    pub encoding: FileEncoding,
+    /// Whether the file starts with a byte order mark.
+    pub bom: bool,
+    pub line_ending: LineEnding,
+}
+
+impl FileFormat {
+    /// Decodes the bytes of a file. A BOM tells UTF-8 and UTF-16 apart, bytes that aren't
+    /// valid UTF-8 are read as Latin-1. Files with "\r\n" on every line are DOS ones, their
+    /// line breaks become "\n" (mixed ones are kept as they are). Also tells whether some bytes
+    /// weren't valid and were replaced, saving the file would lose them.
+    pub fn decode(bytes: &[u8]) -> (String, Self, bool) {
+        let (format, bom_len) = Self::detect(bytes, true);
+        let (text, _, lossy) = format.encoding.decode_prefix(&bytes[bom_len..], true);
+
+        let line_ending = LineEnding::detect(&text);
+        let text = match line_ending {
+            LineEnding::Unix => text,
+            LineEnding::Dos => text.replace("\r\n", "\n"),
+        };
+        let format = FileFormat {
+            line_ending,
+            ..format
+        };
+        (text, format, lossy)
+    }
+
+    /// Encoding of a file from its first bytes, and the length of its BOM. Without
+    /// `complete`, a char cut at the end of `bytes` doesn't make them invalid UTF-8.
+    fn detect(bytes: &[u8], complete: bool) -> (Self, usize) {
+        let (encoding, bom) = if bytes.starts_with(UTF8_BOM) {
+            (FileEncoding::Utf8, UTF8_BOM)
+        } else if bytes.starts_with(UTF16LE_BOM) {
+            (FileEncoding::Utf16Le, UTF16LE_BOM)
+        } else if bytes.starts_with(UTF16BE_BOM) {
+            (FileEncoding::Utf16Be, UTF16BE_BOM)
+        } else {
+            let end = if complete {
+                bytes.len()
+            } else {
+                bytes.len() - incomplete_utf8_tail(bytes)
+            };
+            let encoding = match std::str::from_utf8(&bytes[..end]) {
+                Ok(_) => FileEncoding::Utf8,
+                Err(_) => FileEncoding::Latin1,
+            };
+            (encoding, &[][..])
+        };
+        let format = FileFormat {
+            encoding,
+            bom: !bom.is_empty(),
+            line_ending: LineEnding::Unix,
+        };
+        (format, bom.len())
+    }
+
+    /// Encodes the text of a buffer to be saved.
+    pub fn encode(&self, text: &str) -> anyhow::Result<Vec<u8>> {
+        let text = match self.line_ending {
+            LineEnding::Unix => text.to_string(),
+            LineEnding::Dos => text.replace('\n', "\r\n"),
+        };
+        let mut bytes = Vec::new();
+        match self.encoding {
+            FileEncoding::Utf8 => {
+                if self.bom {
+                    bytes.extend_from_slice(UTF8_BOM);
+                }
+                bytes.extend_from_slice(text.as_bytes());
+            }
+            FileEncoding::Utf16Le => {
+                bytes.extend_from_slice(UTF16LE_BOM);
+                bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+            }
+            FileEncoding::Utf16Be => {
+                bytes.extend_from_slice(UTF16BE_BOM);
+                bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+            }
+            FileEncoding::Latin1 => {
+                for ch in text.chars() {
+                    match u8::try_from(ch) {
+                        Ok(byte) => bytes.push(byte),
+                        Err(_) => anyhow::bail!("Can't write '{ch}' in latin1"),
+                    }
+                }
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Changes the encoding. UTF-16 files always get a BOM, as it's how they are told apart
+    /// when loading them again, and a UTF-8 one keeps it.
+    pub fn set_encoding(&mut self, encoding: FileEncoding) {
+        self.bom = match encoding {
+            FileEncoding::Utf8 => self.bom && self.encoding == FileEncoding::Utf8,
+            FileEncoding::Utf16Le | FileEncoding::Utf16Be => true,
+            FileEncoding::Latin1 => false,
+        };
+        self.encoding = encoding;
+    }
+}
+
+/// Text of UTF-16 units, and whether some were unpaired surrogates and were replaced.
+fn decode_utf16(units: impl Iterator<Item = u16>) -> (String, bool) {
+    let mut invalid = false;
+    let text = char::decode_utf16(units)
+        .map(|ch| {
+            ch.unwrap_or_else(|_| {
+                invalid = true;
+                char::REPLACEMENT_CHARACTER
+            })
+        })
+        .collect();
+    (text, invalid)
+}
+
+impl Display for FileFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.encoding)?;
+        if self.bom && self.encoding == FileEncoding::Utf8 {
+            write!(f, " bom")?;
+        }
+        write!(f, " {}", self.line_ending)
+    }
+}
+
+/// Decodes a file read in chunks, as large files are. Chars cut between two chunks wait for
+/// the next one. The encoding and the line breaks are detected from the first chunk like
+/// `FileFormat::decode` does, but a later chunk that isn't valid UTF-8 makes an ASCII file so
+/// far a Latin-1 one.
+#[derive(Debug, Default)]
+pub struct ChunkDecoder {
+    /// Detected from the first chunk.
+    pub format: Option<FileFormat>,
+    /// Bytes that aren't valid in the encoding were replaced, saving the file would lose them.
+    pub lossy: bool,
+    pending: Vec<u8>,
+    /// Text held until a line break tells the line breaks of the file.
+    held: String,
+    line_ending: Option<LineEnding>,
+    /// A "\r" at the end of the last chunk of a DOS file, which may start a "\r\n".
+    pending_cr: bool,
+    /// Some of the text isn't ASCII, it would read differently as Latin-1.
+    non_ascii: bool,
+}
+
+impl ChunkDecoder {
+    /// Text of the chunk, `last` for the end of the file.
+    pub fn decode(&mut self, chunk: &[u8], last: bool) -> String {
+        self.pending.extend_from_slice(chunk);
+        let mut format = match self.format {
+            Some(format) => format,
+            // Wait for enough bytes to see a BOM.
+            None if self.pending.len() < 4 && !last => return String::new(),
+            None => {
+                let (format, bom_len) = FileFormat::detect(&self.pending, last);
+                self.pending.drain(..bom_len);
+                format
+            }
+        };
+        if format.encoding == FileEncoding::Utf8 {
+            let end = if last {
+                self.pending.len()
+            } else {
+                self.pending.len() - incomplete_utf8_tail(&self.pending)
+            };
+            if std::str::from_utf8(&self.pending[..end]).is_err() && !self.non_ascii && !format.bom
+            {
+                format.encoding = FileEncoding::Latin1;
+            }
+        }
+        let (text, used, lossy) = format.encoding.decode_prefix(&self.pending, last);
+        self.pending.drain(..used);
+        self.lossy |= lossy;
+        self.non_ascii |= !text.is_ascii();
+
+        let mut text = match self.line_ending {
+            Some(_) => text,
+            None if !last && !text.contains('\n') => {
+                self.held.push_str(&text);
+                self.format = Some(format);
+                return String::new();
+            }
+            None => {
+                let text = std::mem::take(&mut self.held) + &text;
+                self.line_ending = Some(LineEnding::detect(&text));
+                text
+            }
+        };
+        format.line_ending = self.line_ending.unwrap_or_default();
+        self.format = Some(format);
+
+        if format.line_ending == LineEnding::Dos {
+            if std::mem::take(&mut self.pending_cr) {
+                text.insert(0, '\r');
+            }
+            if !last && text.ends_with('\r') {
+                text.pop();
+                self.pending_cr = true;
+            }
+            text = text.replace("\r\n", "\n");
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let files: [&[u8]; 5] = [
+            b"a\nb\n",
+            b"\xEF\xBB\xBFa\r\nb\r\n",
+            b"caf\xE9\r\n",
+            b"\xFF\xFEa\x00\r\x00\n\x00",
+            b"mixed\r\nlines\n",
+        ];
+        for bytes in files {
+            let (text, format, lossy) = FileFormat::decode(bytes);
+            assert_eq!(format.encode(&text).unwrap(), bytes);
+            assert!(!lossy);
+        }
+
+        let (text, format, _) = FileFormat::decode(b"caf\xE9\r\n");
+        assert_eq!(text, "café\n");
+        assert_eq!(format.to_string(), "latin1 dos");
+        assert!(format.encode("日本").is_err());
+        let (text, format, _) = FileFormat::decode(b"mixed\r\nlines\n");
+        assert_eq!(text, "mixed\r\nlines\n");
+        assert_eq!(format.line_ending, LineEnding::Unix);
+    }
+
+    #[test]
+    fn test_lossy_decode() {
+        let files: [&[u8]; 3] = [
+            b"\xEF\xBB\xBFcaf\xE9\n",
+            b"\xFF\xFEa\x00\n",
+            b"\xFF\xFE=\xD8a\x00",
+        ];
+        for bytes in files {
+            let (_, _, lossy) = FileFormat::decode(bytes);
+            assert!(lossy);
+        }
+    }
+
+    #[test]
+    fn test_chunk_decoder() {
+        let bytes = "\u{FEFF}日本\r\n🙂".as_bytes();
+        for chunk_len in 1..bytes.len() {
+            let mut decoder = ChunkDecoder::default();
+            let chunks: Vec<&[u8]> = bytes.chunks(chunk_len).collect();
+            let text: String = chunks
+                .iter()
+                .enumerate()
+                .map(|(idx, chunk)| decoder.decode(chunk, idx == chunks.len() - 1))
+                .collect();
+            assert_eq!(text, "日本\n🙂");
+            assert_eq!(decoder.format.unwrap().to_string(), "utf-8 bom dos");
+        }
+
+        let mut decoder = ChunkDecoder::default();
+        let text = decoder.decode(b"a\r\nb\r", false) + &decoder.decode(b"\nc\r\n", true);
+        assert_eq!(text, "a\nb\nc\n");
+
+        // ASCII then Latin-1 bytes, the file is Latin-1.
+        let mut decoder = ChunkDecoder::default();
+        let text = decoder.decode(b"cafe\n", false) + &decoder.decode(b"caf\xE9\n", true);
+        assert_eq!(text, "cafe\ncafé\n");
+        assert_eq!(decoder.format.unwrap().to_string(), "latin1 unix");
+        assert!(!decoder.lossy);
+
+        // UTF-8 then Latin-1 bytes, they can't be read back the way they were.
+        let mut decoder = ChunkDecoder::default();
+        decoder.decode("café\n".as_bytes(), false);
+        decoder.decode(b"caf\xE9\n", true);
+        assert_eq!(decoder.format.unwrap().encoding, FileEncoding::Utf8);
+        assert!(decoder.lossy);
+
+        let mut decoder = ChunkDecoder::default();
+        let text =
+            decoder.decode(b"\xFF\xFE=\xD8", false) + &decoder.decode(b"\x42\xDEa\x00", true);
+        assert_eq!(text, "🙂a");
+    }
+}
@@ -1,7 +1,15 @@
 use anyhow::Result;
 use crossterm::event::{self, KeyCode, KeyModifiers};
+use std::fs::File;
+use std::io::Read;
 use std::sync::mpsc::Sender;
 
+use crate::encoding::{ChunkDecoder, FileFormat};
+
+/// Bytes read at once when loading a large file.
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum EventKind {
     NormalMode,
     Quit,
@@ -15,6 +23,71 @@ pub enum EventKind {
     Tab,
     ShiftTab,
     RequestCompletion,
+    Redo,
+    VisualBlockMode,
+    JumpBack,
+    AddCursorNext,
+    AddCursorBelow,
+    AddCursorAbove,
+    /// Part of a large file loaded in the background, appended to its buffer.
+    FileChunk {
+        file_path: String,
+        text: String,
+        format: FileFormat,
+        /// Some bytes couldn't be decoded, see `ChunkDecoder::lossy`.
+        lossy: bool,
+        done: bool,
+    },
+}
+
+impl EventKind {
+    /// The key as it's stored in a register when recording a macro, special keys use the
+    /// same control chars as vim (e.g. "\x1b" for Esc), and private use chars for the ones
+    /// vim has none for. Quit isn't recorded, so that playing a macro never exits the
+    /// editor, nor are chunks of a large file, which aren't typed.
+    pub fn to_key(&self) -> Option<char> {
+        match self {
+            Self::KeyPressed(ch) | Self::ShiftedKey(ch) => Some(*ch),
+            Self::NormalMode => Some('\x1b'),
+            Self::EnterKey => Some('\r'),
+            Self::Backspace => Some('\x08'),
+            Self::Tab => Some('\t'),
+            Self::ScrollUp => Some('\x15'),
+            Self::ScrollDown => Some('\x04'),
+            Self::SaveFile => Some('\x13'),
+            Self::RequestCompletion => Some('\x18'),
+            Self::Redo => Some('\x12'),
+            Self::VisualBlockMode => Some('\x16'),
+            Self::JumpBack => Some('\x0f'),
+            Self::AddCursorNext => Some('\x0e'),
+            Self::ShiftTab => Some('\u{e000}'),
+            Self::AddCursorBelow => Some('\u{e001}'),
+            Self::AddCursorAbove => Some('\u{e002}'),
+            Self::Quit | Self::FileChunk { .. } => None,
+        }
+    }
+
+    pub fn from_key(key: char) -> Self {
+        match key {
+            '\x1b' => Self::NormalMode,
+            '\r' | '\n' => Self::EnterKey,
+            '\x08' => Self::Backspace,
+            '\t' => Self::Tab,
+            '\x15' => Self::ScrollUp,
+            '\x04' => Self::ScrollDown,
+            '\x13' => Self::SaveFile,
+            '\x18' => Self::RequestCompletion,
+            '\x12' => Self::Redo,
+            '\x16' => Self::VisualBlockMode,
+            '\x0f' => Self::JumpBack,
+            '\x0e' => Self::AddCursorNext,
+            '\u{e000}' => Self::ShiftTab,
+            '\u{e001}' => Self::AddCursorBelow,
+            '\u{e002}' => Self::AddCursorAbove,
+            ch if ch.is_uppercase() => Self::ShiftedKey(ch),
+            ch => Self::KeyPressed(ch),
+        }
+    }
 }
 
 pub fn handle_events(sender: Sender<EventKind>) -> Result<()> {
@@ -27,6 +100,14 @@ pub fn handle_events(sender: Sender<EventKind>) -> Result<()> {
                     KeyCode::Char('d') => sender.send(EventKind::ScrollDown)?,
                     KeyCode::Char('s') => sender.send(EventKind::SaveFile)?,
                     KeyCode::Char('x') => sender.send(EventKind::RequestCompletion)?,
+                    KeyCode::Char('r') => sender.send(EventKind::Redo)?,
+                    KeyCode::Char('v') => sender.send(EventKind::VisualBlockMode)?,
+                    KeyCode::Char('o') => sender.send(EventKind::JumpBack)?,
+                    // Most terminals send Tab for Ctrl+i, it jumps forward in Normal mode.
+                    KeyCode::Char('i') => sender.send(EventKind::Tab)?,
+                    KeyCode::Char('n') => sender.send(EventKind::AddCursorNext)?,
+                    KeyCode::Down => sender.send(EventKind::AddCursorBelow)?,
+                    KeyCode::Up => sender.send(EventKind::AddCursorAbove)?,
                     _ => {}
                 }
             } else if key.code == KeyCode::BackTab {
@@ -48,3 +129,60 @@ pub fn handle_events(sender: Sender<EventKind>) -> Result<()> {
         }
     }
 }
+
+/// Reads a large file in chunks and sends them as `FileChunk` events, so that the start of
+/// it can be shown and edited while the rest loads.
+pub fn load_file_chunks(file_path: String, sender: Sender<EventKind>) -> Result<()> {
+    let mut file = File::open(&file_path)?;
+    let mut decoder = ChunkDecoder::default();
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut chunk)?;
+        let done = read == 0;
+        let text = decoder.decode(&chunk[..read], done);
+        sender.send(EventKind::FileChunk {
+            file_path: file_path.clone(),
+            text,
+            format: decoder.format.unwrap_or_default(),
+            lossy: decoder.lossy,
+            done,
+        })?;
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_macro_keys() {
+        let events = [
+            EventKind::NormalMode,
+            EventKind::KeyPressed('a'),
+            EventKind::Backspace,
+            EventKind::ScrollUp,
+            EventKind::ScrollDown,
+            EventKind::SaveFile,
+            EventKind::EnterKey,
+            EventKind::ShiftedKey('A'),
+            EventKind::Tab,
+            EventKind::ShiftTab,
+            EventKind::RequestCompletion,
+            EventKind::Redo,
+            EventKind::VisualBlockMode,
+            EventKind::JumpBack,
+            EventKind::AddCursorNext,
+            EventKind::AddCursorBelow,
+            EventKind::AddCursorAbove,
+        ];
+        for event in events {
+            let key = event.to_key().unwrap();
+            assert_eq!(EventKind::from_key(key), event);
+        }
+        assert_eq!(EventKind::Quit.to_key(), None);
+        assert_eq!(EventKind::from_key('\x03'), EventKind::KeyPressed('\x03'));
+    }
+}
@@ -0,0 +1,635 @@
+use crate::buffer::types::{Case, CharSearch, Motion, TextObject};
+use crate::events::EventKind;
+use crate::registers::Registers;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,     // "d"
+    Change,     // "c"
+    Yank,       // "y"
+    Indent,     // ">"
+    Dedent,     // "<"
+    Comment,    // "gc"
+    Case(Case), // "gu", "gU", "g~"
+}
+
+impl Operator {
+    pub fn from_char(ch: char) -> Option<Self> {
+        match ch {
+            'd' => Some(Self::Delete),
+            'c' => Some(Self::Change),
+            'y' => Some(Self::Yank),
+            '>' => Some(Self::Indent),
+            '<' => Some(Self::Dedent),
+            _ => None,
+        }
+    }
+
+    /// Operators typed after "g", e.g. "gc".
+    pub fn from_g_char(ch: char) -> Option<Self> {
+        match ch {
+            'c' => Some(Self::Comment),
+            'u' => Some(Self::Case(Case::Lower)),
+            'U' => Some(Self::Case(Case::Upper)),
+            '~' => Some(Self::Case(Case::Toggle)),
+            _ => None,
+        }
+    }
+
+    /// Parses the operator at the start of `chars`, along with how many keys it takes.
+    fn parse(chars: &[char]) -> Option<(Self, usize)> {
+        match chars {
+            ['g', ch, ..] => Some((Self::from_g_char(*ch)?, 2)),
+            [ch, ..] => Some((Self::from_char(*ch)?, 1)),
+            [] => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Move(Motion),                           // "w", "$", ...
+    Operate(Operator, Motion),              // "dw", "c$", ...
+    OperateObject(Operator, TextObject),    // "diw", "ca(", ...
+    OperateLines(Operator),                 // "dd", "yy", ">>", ...
+    OperateSelection(Operator),             // "d" on a visual selection
+    SelectObject(TextObject),               // "iw", "a\"", ... on a visual selection
+    SplitSelection,                         // "S", one cursor per selected line
+    ToggleDiagnostics,                      // "["
+    CommandMode,                            // ":"
+    VisualMode,                             // "v"
+    VisualLineMode,                         // "V"
+    Insert,                                 // "i"
+    InsertLineBelow,                        // "o"
+    InsertLineStart,                        // "I", block insert on a visual block
+    ReplaceMode,                            // "R"
+    ReplaceChar(char),                      // "rx"
+    ToggleCase,                             // "~"
+    JoinLines { normalize: bool },          // "J", "gJ" keeps the whitespace as it is
+    AppendLineEnd,                          // "A", block append on a visual block
+    Paste,                                  // "p"
+    PasteBefore,                            // "P"
+    Undo,                                   // "u"
+    Repeat,                                 // "."
+    RecordMacro(char),                      // "qa", "q" again stops recording
+    PlayMacro(char),                        // "@a", "@@" plays the last one again
+    SetMark(char),                          // "ma", "mA"
+    JumpToMark { mark: char, exact: bool }, // "'a" to the line, "`a" to the exact position
+    Hover,                                  // "K"
+    Search { forward: bool },               // "/" and "?"
+    SearchNext { reverse: bool },           // "n" and "N"
+    SearchWord { forward: bool },           // "*" and "#"
+}
+
+/// The last change made from Normal mode, replayed by ".".
+#[derive(Debug, Clone)]
+pub struct LastChange {
+    pub action: Action,
+    pub count: Option<usize>,
+    pub register: Option<char>,
+    /// What was typed in the Insert session that followed the action, if it started one.
+    pub inserted: Vec<Inserted>,
+}
+
+/// Part of the Insert session of a change.
+#[derive(Debug, Clone)]
+pub enum Inserted {
+    /// A key, replayed as such so that auto-indent and auto-pairs apply again.
+    Key(EventKind),
+    /// The label of an accepted LSP completion, which replaced the word before the cursor.
+    /// Requesting completions again could give other ones.
+    Completion(String),
+}
+
+/// Result of parsing the keys typed so far in Normal or Visual mode.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Parsed {
+    /// A complete action, along with the count and register typed before it (if any).
+    Action(Action, Option<usize>, Option<char>),
+    /// The keys are a valid prefix, wait for more of them.
+    Pending,
+    Invalid,
+}
+
+/// Splits a count (e.g. "12" in "12dd") from the start of `chars`. A leading "0" is not a
+/// count, but the start of line motion.
+fn split_count(chars: &[char]) -> (Option<usize>, &[char]) {
+    if chars.first().is_none_or(|c| *c == '0') {
+        return (None, chars);
+    }
+    let digits = chars.iter().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 {
+        return (None, chars);
+    }
+    let count = chars[..digits].iter().collect::<String>().parse().ok();
+    (count, &chars[digits..])
+}
+
+/// Parses the motion at the start of `chars` into the action made by `action`, or returns
+/// `None` if there's no motion there. "f", "t", "F" and "T" wait for the char to find, and
+/// "g" for the second key of "gg", "gj" or "gk".
+fn parse_motion(
+    chars: &[char],
+    count: Option<usize>,
+    action: impl FnOnce(Motion) -> Action,
+) -> Option<Parsed> {
+    let &first = chars.first()?;
+    let motion = if "ftFT".contains(first) {
+        match chars.get(1) {
+            None => return Some(Parsed::Pending),
+            Some(&ch) => Motion::FindChar(CharSearch::new(first, ch)?),
+        }
+    } else if first == 'g' {
+        match chars.get(1) {
+            None => return Some(Parsed::Pending),
+            Some('g') => Motion::FileStart,
+            Some('j') => Motion::DisplayDown,
+            Some('k') => Motion::DisplayUp,
+            Some(_) => return None,
+        }
+    } else {
+        Motion::from_char(first)?
+    };
+    Some(Parsed::Action(action(motion), count, None))
+}
+
+/// Multiplies two optional counts, so that "2d3w" deletes six words.
+fn combine_counts(first: Option<usize>, second: Option<usize>) -> Option<usize> {
+    match (first, second) {
+        (Some(a), Some(b)) => Some(a.saturating_mul(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+impl Action {
+    /// Whether the action changes the buffer, so that "." can repeat it.
+    pub fn is_change(&self) -> bool {
+        match self {
+            Self::Operate(operator, _)
+            | Self::OperateObject(operator, _)
+            | Self::OperateLines(operator) => *operator != Operator::Yank,
+            Self::Insert
+            | Self::InsertLineBelow
+            | Self::InsertLineStart
+            | Self::AppendLineEnd
+            | Self::ReplaceMode
+            | Self::ReplaceChar(_)
+            | Self::ToggleCase
+            | Self::JoinLines { .. }
+            | Self::Paste
+            | Self::PasteBefore => true,
+            _ => false,
+        }
+    }
+
+    pub fn parse(keys: &str, visual: bool) -> Parsed {
+        let chars: Vec<char> = keys.chars().collect();
+        let (count, chars) = split_count(&chars);
+
+        // A register ("a, "+, ...) can be given before or after the count.
+        if chars.first() == Some(&'"') {
+            return match chars.get(1) {
+                None => Parsed::Pending,
+                Some(&name) if Registers::is_valid(name) => {
+                    match Self::parse_action(&chars[2..], visual) {
+                        Parsed::Action(action, second_count, _) => {
+                            Parsed::Action(action, combine_counts(count, second_count), Some(name))
+                        }
+                        parsed => parsed,
+                    }
+                }
+                Some(_) => Parsed::Invalid,
+            };
+        }
+
+        match Self::parse_action(chars, visual) {
+            Parsed::Action(action, second_count, _) => {
+                Parsed::Action(action, combine_counts(count, second_count), None)
+            }
+            parsed => parsed,
+        }
+    }
+
+    /// Parses the keys after the count and register.
+    fn parse_action(chars: &[char], visual: bool) -> Parsed {
+        let (count, chars) = split_count(chars);
+        let Some(&first) = chars.first() else {
+            return Parsed::Pending;
+        };
+
+        if chars == ['g'] {
+            return Parsed::Pending;
+        }
+
+        if let Some((operator, len)) = Operator::parse(chars) {
+            if visual {
+                return Parsed::Action(Self::OperateSelection(operator), count, None);
+            }
+            // Doubling the last key of the operator makes it work on lines ("dd", "gcc").
+            let last_key = chars[len - 1];
+            let (motion_count, rest) = split_count(&chars[len..]);
+            let count = combine_counts(count, motion_count);
+            return match rest.first() {
+                None => Parsed::Pending,
+                Some(&second) if second == last_key && motion_count.is_none() => {
+                    Parsed::Action(Self::OperateLines(operator), count, None)
+                }
+                Some(&second) if second == 'i' || second == 'a' => match rest.get(1) {
+                    None => Parsed::Pending,
+                    Some(&ch) => match TextObject::from_chars(second, ch) {
+                        Some(object) => {
+                            Parsed::Action(Self::OperateObject(operator, object), count, None)
+                        }
+                        None => Parsed::Invalid,
+                    },
+                },
+                Some(_) => parse_motion(rest, count, |motion| Self::Operate(operator, motion))
+                    .unwrap_or(Parsed::Invalid),
+            };
+        }
+
+        if visual && (first == 'i' || first == 'a') {
+            return match chars.get(1) {
+                None => Parsed::Pending,
+                Some(&ch) => match TextObject::from_chars(first, ch) {
+                    Some(object) => Parsed::Action(Self::SelectObject(object), count, None),
+                    None => Parsed::Invalid,
+                },
+            };
+        }
+
+        if let Some(parsed) = parse_motion(chars, count, Self::Move) {
+            return parsed;
+        }
+
+        if !visual && (first == 'q' || first == '@') {
+            return match chars.get(1) {
+                None => Parsed::Pending,
+                Some(&'@') if first == '@' => Parsed::Action(Self::PlayMacro('@'), count, None),
+                Some(&name) if Registers::is_valid(name) && name != '_' => {
+                    let action = if first == 'q' {
+                        Self::RecordMacro(name)
+                    } else {
+                        Self::PlayMacro(name)
+                    };
+                    Parsed::Action(action, count, None)
+                }
+                Some(_) => Parsed::Invalid,
+            };
+        }
+
+        if chars.starts_with(&['g', 'J']) {
+            return Parsed::Action(Self::JoinLines { normalize: false }, count, None);
+        }
+
+        if first == 'r' && !visual {
+            return match chars.get(1) {
+                None => Parsed::Pending,
+                Some(&ch) => Parsed::Action(Self::ReplaceChar(ch), count, None),
+            };
+        }
+
+        if first == 'm' && !visual {
+            return match chars.get(1) {
+                None => Parsed::Pending,
+                Some(&mark) if mark.is_ascii_alphabetic() => {
+                    Parsed::Action(Self::SetMark(mark), count, None)
+                }
+                Some(_) => Parsed::Invalid,
+            };
+        }
+
+        if first == '\'' || first == '`' {
+            return match chars.get(1) {
+                None => Parsed::Pending,
+                Some(&mark) if mark.is_ascii_alphabetic() || "'`<>".contains(mark) => {
+                    let exact = first == '`';
+                    Parsed::Action(Self::JumpToMark { mark, exact }, count, None)
+                }
+                Some(_) => Parsed::Invalid,
+            };
+        }
+
+        let action = match first {
+            '[' => Self::ToggleDiagnostics,
+            ':' => Self::CommandMode,
+            'v' => Self::VisualMode,
+            'V' => Self::VisualLineMode,
+            'i' if !visual => Self::Insert,
+            'o' if !visual => Self::InsertLineBelow,
+            'I' => Self::InsertLineStart,
+            'A' => Self::AppendLineEnd,
+            'S' if visual => Self::SplitSelection,
+            'p' if !visual => Self::Paste,
+            'P' if !visual => Self::PasteBefore,
+            'u' if !visual => Self::Undo,
+            'R' if !visual => Self::ReplaceMode,
+            'J' => Self::JoinLines { normalize: true },
+            '~' if !visual => Self::ToggleCase,
+            '~' => Self::OperateSelection(Operator::Case(Case::Toggle)),
+            'u' => Self::OperateSelection(Operator::Case(Case::Lower)),
+            'U' => Self::OperateSelection(Operator::Case(Case::Upper)),
+            '.' if !visual => Self::Repeat,
+            '/' if !visual => Self::Search { forward: true },
+            '?' if !visual => Self::Search { forward: false },
+            'n' => Self::SearchNext { reverse: false },
+            'N' => Self::SearchNext { reverse: true },
+            '*' => Self::SearchWord { forward: true },
+            '#' => Self::SearchWord { forward: false },
+            'K' if !visual => Self::Hover,
+            _ => return Parsed::Invalid,
+        };
+        Parsed::Action(action, count, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_operator_pending() {
+        assert_eq!(Action::parse("d", false), Parsed::Pending);
+        assert_eq!(
+            Action::parse("dw", false),
+            Parsed::Action(
+                Action::Operate(Operator::Delete, Motion::NextWord),
+                None,
+                None
+            )
+        );
+        assert_eq!(
+            Action::parse("yy", false),
+            Parsed::Action(Action::OperateLines(Operator::Yank), None, None)
+        );
+        assert_eq!(
+            Action::parse("c", true),
+            Parsed::Action(Action::OperateSelection(Operator::Change), None, None)
+        );
+        assert_eq!(Action::parse("dx", false), Parsed::Invalid);
+        assert_eq!(Action::parse("di", false), Parsed::Pending);
+        assert_eq!(
+            Action::parse("ci(", false),
+            Parsed::Action(
+                Action::OperateObject(
+                    Operator::Change,
+                    TextObject::Brackets {
+                        open: '(',
+                        close: ')',
+                        around: false
+                    }
+                ),
+                None,
+                None
+            )
+        );
+        assert_eq!(
+            Action::parse("aw", true),
+            Parsed::Action(
+                Action::SelectObject(TextObject::Word { around: true }),
+                None,
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_registers() {
+        assert_eq!(Action::parse("\"", false), Parsed::Pending);
+        assert_eq!(Action::parse("\"a", false), Parsed::Pending);
+        assert_eq!(
+            Action::parse("\"ayy", false),
+            Parsed::Action(Action::OperateLines(Operator::Yank), None, Some('a'))
+        );
+        assert_eq!(
+            Action::parse("2\"+3P", false),
+            Parsed::Action(Action::PasteBefore, Some(6), Some('+'))
+        );
+        assert_eq!(Action::parse("\"!", false), Parsed::Invalid);
+        assert_eq!(
+            Action::parse("qa", false),
+            Parsed::Action(Action::RecordMacro('a'), None, None)
+        );
+        assert_eq!(
+            Action::parse("3@@", false),
+            Parsed::Action(Action::PlayMacro('@'), Some(3), None)
+        );
+    }
+
+    #[test]
+    fn test_parse_comment() {
+        assert_eq!(Action::parse("g", false), Parsed::Pending);
+        assert_eq!(Action::parse("gc", false), Parsed::Pending);
+        assert_eq!(
+            Action::parse("3gcc", false),
+            Parsed::Action(Action::OperateLines(Operator::Comment), Some(3), None)
+        );
+        assert_eq!(
+            Action::parse("gcip", false),
+            Parsed::Action(
+                Action::OperateObject(Operator::Comment, TextObject::Paragraph { around: false }),
+                None,
+                None
+            )
+        );
+        assert_eq!(
+            Action::parse("gc", true),
+            Parsed::Action(Action::OperateSelection(Operator::Comment), None, None)
+        );
+        assert_eq!(Action::parse("gx", false), Parsed::Invalid);
+    }
+
+    #[test]
+    fn test_parse_char_search() {
+        assert_eq!(Action::parse("f", false), Parsed::Pending);
+        assert_eq!(Action::parse("dt", false), Parsed::Pending);
+        let search = CharSearch {
+            ch: ')',
+            forward: true,
+            till: true,
+        };
+        assert_eq!(
+            Action::parse("dt)", false),
+            Parsed::Action(
+                Action::Operate(Operator::Delete, Motion::FindChar(search)),
+                None,
+                None
+            )
+        );
+        assert_eq!(
+            Action::parse("2F1", true),
+            Parsed::Action(
+                Action::Move(Motion::FindChar(CharSearch {
+                    ch: '1',
+                    forward: false,
+                    till: false,
+                })),
+                Some(2),
+                None
+            )
+        );
+        assert_eq!(
+            Action::parse(",", false),
+            Parsed::Action(
+                Action::Move(Motion::RepeatFind { reverse: true }),
+                None,
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_structural_motions() {
+        assert_eq!(
+            Action::parse("gg", false),
+            Parsed::Action(Action::Move(Motion::FileStart), None, None)
+        );
+        assert_eq!(
+            Action::parse("12G", true),
+            Parsed::Action(Action::Move(Motion::FileEnd), Some(12), None)
+        );
+        assert_eq!(Action::parse("dg", false), Parsed::Pending);
+        assert_eq!(
+            Action::parse("dgg", false),
+            Parsed::Action(
+                Action::Operate(Operator::Delete, Motion::FileStart),
+                None,
+                None
+            )
+        );
+        assert_eq!(
+            Action::parse("y%", false),
+            Parsed::Action(
+                Action::Operate(Operator::Yank, Motion::MatchingBracket),
+                None,
+                None
+            )
+        );
+        assert_eq!(
+            Action::parse("c}", false),
+            Parsed::Action(
+                Action::Operate(Operator::Change, Motion::ParagraphForward),
+                None,
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_replace_and_case() {
+        assert_eq!(Action::parse("r", false), Parsed::Pending);
+        assert_eq!(
+            Action::parse("3rx", false),
+            Parsed::Action(Action::ReplaceChar('x'), Some(3), None)
+        );
+        assert_eq!(
+            Action::parse("gUiw", false),
+            Parsed::Action(
+                Action::OperateObject(
+                    Operator::Case(Case::Upper),
+                    TextObject::Word { around: false }
+                ),
+                None,
+                None
+            )
+        );
+        assert_eq!(
+            Action::parse("g~~", false),
+            Parsed::Action(
+                Action::OperateLines(Operator::Case(Case::Toggle)),
+                None,
+                None
+            )
+        );
+        assert_eq!(
+            Action::parse("gu", true),
+            Parsed::Action(
+                Action::OperateSelection(Operator::Case(Case::Lower)),
+                None,
+                None
+            )
+        );
+        assert_eq!(
+            Action::parse("U", true),
+            Parsed::Action(
+                Action::OperateSelection(Operator::Case(Case::Upper)),
+                None,
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_join() {
+        assert_eq!(
+            Action::parse("3J", false),
+            Parsed::Action(Action::JoinLines { normalize: true }, Some(3), None)
+        );
+        assert_eq!(
+            Action::parse("gJ", true),
+            Parsed::Action(Action::JoinLines { normalize: false }, None, None)
+        );
+    }
+
+    #[test]
+    fn test_parse_marks() {
+        assert_eq!(Action::parse("m", false), Parsed::Pending);
+        assert_eq!(
+            Action::parse("mA", false),
+            Parsed::Action(Action::SetMark('A'), None, None)
+        );
+        assert_eq!(Action::parse("m1", false), Parsed::Invalid);
+        assert_eq!(
+            Action::parse("'a", true),
+            Parsed::Action(
+                Action::JumpToMark {
+                    mark: 'a',
+                    exact: false
+                },
+                None,
+                None
+            )
+        );
+        assert_eq!(
+            Action::parse("``", false),
+            Parsed::Action(
+                Action::JumpToMark {
+                    mark: '`',
+                    exact: true
+                },
+                None,
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_counts() {
+        assert_eq!(Action::parse("1", false), Parsed::Pending);
+        assert_eq!(
+            Action::parse("0", false),
+            Parsed::Action(Action::Move(Motion::LineStart), None, None)
+        );
+        assert_eq!(
+            Action::parse("10j", false),
+            Parsed::Action(Action::Move(Motion::Down), Some(10), None)
+        );
+        assert_eq!(
+            Action::parse("10dd", false),
+            Parsed::Action(Action::OperateLines(Operator::Delete), Some(10), None)
+        );
+        assert_eq!(
+            Action::parse("3.", false),
+            Parsed::Action(Action::Repeat, Some(3), None)
+        );
+        assert_eq!(Action::parse("2d3", false), Parsed::Pending);
+        assert_eq!(
+            Action::parse("2d3w", false),
+            Parsed::Action(
+                Action::Operate(Operator::Delete, Motion::NextWord),
+                Some(6),
+                None
+            )
+        );
+    }
+}
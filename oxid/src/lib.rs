@@ -1,7 +1,11 @@
 pub mod app;
 pub mod buffer;
 pub mod cli;
-pub mod events;
-pub mod ui;
 pub mod command;
 pub mod config;
+pub mod encoding;
+pub mod events;
+pub mod keymap;
+pub mod registers;
+pub mod search;
+pub mod ui;
@@ -1,28 +1,44 @@
 use anyhow::Result;
 use ropey::Rope;
-use std::fs::File;
-use std::io::BufReader;
 use std::sync::mpsc::channel;
 
 use oxid::app::App;
 use oxid::buffer::Buffer;
-use oxid::events::{EventKind, handle_events};
+use oxid::encoding::FileFormat;
+use oxid::events::{EventKind, handle_events, load_file_chunks};
 
 fn main() -> Result<()> {
     let mut terminal = ratatui::init();
 
     let file_path = oxid::cli::get_file_name_arg()?;
-    let file_text = Rope::from_reader(BufReader::new(File::open(&file_path)?))?;
     let config = oxid::config::read_config_file().unwrap_or_default();
+    let (event_sender, event_receiver) = channel::<EventKind>();
 
     let tsize_x = terminal.size()?.width as usize;
     let tsize_y = terminal.size()?.height as usize;
 
-    let mut buffers: Vec<Buffer> = Vec::new();
-    buffers.push(Buffer::new(Some(file_path), file_text, tsize_x, tsize_y));
+    let buffer = if config.is_large_file(&file_path) {
+        let sender = event_sender.clone();
+        let path = file_path.clone();
+        std::thread::spawn(move || load_file_chunks(path, sender));
+        Buffer::new_large_file(file_path, tsize_x, tsize_y)
+    } else {
+        let (file_text, file_format, lossy) = FileFormat::decode(&std::fs::read(&file_path)?);
+        let mut buffer = Buffer::new(
+            Some(file_path),
+            Rope::from_str(&file_text),
+            tsize_x,
+            tsize_y,
+        );
+        buffer.file_format = file_format;
+        buffer.lossy = lossy;
+        buffer.load_undo_file();
+        buffer
+    };
+    let buffers: Vec<Buffer> = vec![buffer];
 
     let mut app = App::new(buffers, tsize_x, tsize_y, config);
-    let (event_sender, event_receiver) = channel::<EventKind>();
+    app.event_sender = Some(event_sender.clone());
     std::thread::spawn(move || handle_events(event_sender));
     let result = app.run(event_receiver, &mut terminal);
 
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+/// Content of a register, `linewise` when it holds whole lines (e.g. yanked with "yy").
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Register {
+    pub text: String,
+    pub linewise: bool,
+}
+
+/// Vim-like registers:
+/// - `"` unnamed, written by every yank and delete and used when no register is given.
+/// - `0` last yank.
+/// - `1`-`9` last deletes of one or more lines, shifted on each new one.
+/// - `-` last delete smaller than a line.
+/// - `a`-`z` named, `A`-`Z` append to them.
+/// - `+` system clipboard, written through OSC 52.
+/// - `_` black hole, anything written to it is dropped.
+#[derive(Debug, Default)]
+pub struct Registers {
+    registers: HashMap<char, Register>,
+}
+
+pub const UNNAMED: char = '"';
+
+impl Registers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_valid(name: char) -> bool {
+        name.is_ascii_alphanumeric() || matches!(name, '"' | '-' | '+' | '_')
+    }
+
+    pub fn get(&self, name: Option<char>) -> Option<&Register> {
+        let name = name.unwrap_or(UNNAMED).to_ascii_lowercase();
+        self.registers.get(&name).filter(|reg| !reg.text.is_empty())
+    }
+
+    pub fn yank(&mut self, name: Option<char>, text: String, linewise: bool) {
+        let register = Register { text, linewise };
+        match name {
+            Some('_') => {}
+            None | Some(UNNAMED) => {
+                self.registers.insert('0', register.clone());
+                self.registers.insert(UNNAMED, register);
+            }
+            Some(name) => self.write_named(name, register),
+        }
+    }
+
+    pub fn delete(&mut self, name: Option<char>, text: String, linewise: bool) {
+        let register = Register { text, linewise };
+        match name {
+            Some('_') => {}
+            None | Some(UNNAMED) => {
+                if linewise || register.text.contains('\n') {
+                    for idx in (1..9).rev() {
+                        let from = char::from_digit(idx, 10).unwrap_or('1');
+                        let to = char::from_digit(idx + 1, 10).unwrap_or('9');
+                        if let Some(reg) = self.registers.remove(&from) {
+                            self.registers.insert(to, reg);
+                        }
+                    }
+                    self.registers.insert('1', register.clone());
+                } else {
+                    self.registers.insert('-', register.clone());
+                }
+                self.registers.insert(UNNAMED, register);
+            }
+            Some(name) => self.write_named(name, register),
+        }
+    }
+
+    /// Stores the keys of a recorded macro, without touching the unnamed register.
+    pub fn record_macro(&mut self, name: char, keys: String) {
+        if name != '_' {
+            self.store(
+                name,
+                Register {
+                    text: keys,
+                    linewise: false,
+                },
+            );
+        }
+    }
+
+    fn write_named(&mut self, name: char, register: Register) {
+        let register = self.store(name, register);
+        self.registers.insert(UNNAMED, register);
+    }
+
+    /// Writes a named register, or appends to it for `A`-`Z`, and returns its new content.
+    fn store(&mut self, name: char, register: Register) -> Register {
+        let register = if name.is_ascii_uppercase() {
+            // Appending to a register, linewise content always starts on a new line.
+            let name = name.to_ascii_lowercase();
+            let mut current = self.registers.remove(&name).unwrap_or_default();
+            if (current.linewise || register.linewise)
+                && !current.text.is_empty()
+                && !current.text.ends_with('\n')
+            {
+                current.text.push('\n');
+            }
+            current.text.push_str(&register.text);
+            current.linewise |= register.linewise;
+            current
+        } else {
+            register
+        };
+
+        if name == '+' {
+            copy_to_clipboard(&register.text);
+        }
+        self.registers
+            .insert(name.to_ascii_lowercase(), register.clone());
+        register
+    }
+}
+
+/// Sets the system clipboard with an OSC 52 escape sequence, which the terminal handles
+/// even when the editor runs over SSH. Inside tmux the sequence has to be wrapped so it's
+/// passed through to the outer terminal.
+fn copy_to_clipboard(text: &str) {
+    let osc = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    let sequence = if std::env::var_os("TMUX").is_some() {
+        format!("\x1bPtmux;{}\x1b\\", osc.replace('\x1b', "\x1b\x1b"))
+    } else {
+        osc
+    };
+    let mut stdout = std::io::stdout();
+    _ = stdout.write_all(sequence.as_bytes());
+    _ = stdout.flush();
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut registers = Registers::new();
+        registers.yank(None, String::from("word"), false);
+        registers.delete(None, String::from("line\n"), true);
+        assert_eq!(registers.get(Some('0')).unwrap().text, "word");
+        assert_eq!(registers.get(Some('1')).unwrap().text, "line\n");
+        assert!(registers.get(None).unwrap().linewise);
+
+        registers.delete(None, String::from("x"), false);
+        assert_eq!(registers.get(Some('-')).unwrap().text, "x");
+        assert_eq!(registers.get(Some('1')).unwrap().text, "line\n");
+
+        registers.yank(Some('a'), String::from("one\n"), true);
+        registers.yank(Some('A'), String::from("two"), false);
+        let a = registers.get(Some('a')).unwrap();
+        assert_eq!(a.text, "one\ntwo");
+        assert!(a.linewise);
+
+        registers.delete(Some('_'), String::from("gone"), false);
+        assert_eq!(registers.get(None).unwrap().text, "one\ntwo");
+    }
+}
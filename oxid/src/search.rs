@@ -0,0 +1,29 @@
+use regex::Regex;
+
+/// A search typed after "/" or "?", or started with "*" / "#".
+#[derive(Debug, Clone)]
+pub struct Search {
+    pub pattern: String,
+    pub regex: Regex,
+    pub forward: bool,
+}
+
+impl Search {
+    /// Returns `None` while the pattern is not a valid regex, e.g. "foo(" as it's typed.
+    pub fn new(pattern: &str, forward: bool) -> Option<Self> {
+        if pattern.is_empty() {
+            return None;
+        }
+        let regex = Regex::new(pattern).ok()?;
+        Some(Search {
+            pattern: pattern.to_string(),
+            regex,
+            forward,
+        })
+    }
+
+    /// Search for a whole word, like "*" and "#" do.
+    pub fn word(word: &str, forward: bool) -> Option<Self> {
+        Self::new(&format!(r"\b{}\b", regex::escape(word)), forward)
+    }
+}
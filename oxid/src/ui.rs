@@ -1,8 +1,8 @@
 pub(super) mod command;
-pub(super) mod debug;
 pub(super) mod completion;
-pub(super) mod hover;
+pub(super) mod debug;
 pub(super) mod diagnostics;
 pub mod editor_view;
+pub(super) mod hover;
 
 pub use editor_view::ui;
@@ -43,8 +43,9 @@ impl<'a> CommandPopup<'a> {
             style: self.style,
         }
     }
-    pub fn content(self, content: &'a str) -> Self {
-        let mut prefix = String::from(":");
+    /// Sets the typed text, shown after the `prefix` of the prompt (":", "/" or "?").
+    pub fn content(self, prefix: char, content: &'a str) -> Self {
+        let mut prefix = String::from(prefix);
         prefix.push_str(content);
 
         Self {
@@ -2,12 +2,16 @@ use ratatui::{
     Frame,
     layout::{Constraint, Direction, Layout, Position},
     prelude::{Alignment, Stylize},
-    style::{Color, Style, palette::tailwind::PURPLE},
+    style::{
+        Color, Style,
+        palette::tailwind::{AMBER, PURPLE},
+    },
     text::{Line, Span},
     widgets::{Block, Paragraph, Wrap},
 };
 
 use crate::buffer::STATUSBAR_SPACE;
+use crate::buffer::types::{BufferPosition, SelectionKind};
 use crate::ui::{command::CommandPopup, completion::render_completion_table, debug::DebugPopup};
 use crate::{
     app::{App, modes::Mode},
@@ -37,19 +41,8 @@ pub fn ui(frame: &mut Frame, app: &App) {
 
     // Get visible lines for the current viewport
     let visible_lines = app.buffers[app.current_buf_index].get_visible_lines();
-
-    // Render line numbers for only the visible lines
-    let numbar_area = top_area[0];
-    let nums_of_lines = {
-        let mut vec_nums: Vec<String> = Vec::new();
-        let start_line = app.buffers[app.current_buf_index].vertical_scroll;
-        for (i, _) in visible_lines.iter().enumerate() {
-            vec_nums.push((start_line + i).to_string())
-        }
-        vec_nums
-    };
-    let numbar_text = Paragraph::new(nums_of_lines.join("\n")).style(Color::Rgb(164, 160, 232));
-    frame.render_widget(numbar_text, numbar_area);
+    // Line numbers of the visible rows, wrapped lines get a marker on their extra rows.
+    let mut nums_of_lines: Vec<String> = Vec::new();
 
     let editor_area = top_area[1];
     let editor_area_chunks = Layout::default()
@@ -65,45 +58,96 @@ pub fn ui(frame: &mut Frame, app: &App) {
     let start_line = app.buffers[app.current_buf_index].vertical_scroll;
     let numbar_space = app.buffers[app.current_buf_index].numbar_space;
 
+    let cursors = &app.buffers[app.current_buf_index].cursors;
     let diagnostics = app.diagnostics.clone().unwrap_or_default();
-    for (i, visible_line) in visible_lines.iter().enumerate() {
+    // Char ranges of the search matches on the visible lines.
+    let search_matches = match &app.search {
+        Some(search) if app.show_search_highlight => {
+            let buffer = &app.buffers[app.current_buf_index];
+            buffer.search_matches(&search.regex, start_line..start_line + visible_lines.len())
+        }
+        _ => Vec::new(),
+    };
+    for i in 0..visible_lines.len() {
         let line_content =
-            app.buffers[app.current_buf_index].get_visible_line_content(visible_line.to_owned());
+            app.buffers[app.current_buf_index].get_visible_line_content(start_line + i);
         let mut spans: Vec<Span> = Vec::new();
+        // Columns of a block selection on this line, the same ones operators act on.
+        let block_columns = match selection {
+            Some(sel) if sel.kind == SelectionKind::Block => {
+                let (first, last, _, _) = sel.bounds(numbar_space);
+                (first..=last)
+                    .contains(&(start_line + i))
+                    .then(|| app.buffers[app.current_buf_index].block_columns(sel, start_line + i))
+            }
+            _ => None,
+        };
 
-        for (char_idx, ch) in line_content.chars().enumerate() {
+        for (col, text) in line_content {
             let abs_line = start_line + i;
             // If selection, check if char is inside of it, if no selection, just pass.
-            let in_selection = if let Some(sel) = selection {
-                // Normalize selection, even if it went backwards, so that it's always start < end.
-                // Always take into account that for rendering, we need to sub numbar_space to
-                // compare in terms of line length!!
-                let (start, end) = if sel.start.line < sel.end.line
-                    || (sel.start.line == sel.end.line
-                        && sel.start.character - numbar_space <= sel.end.character - numbar_space)
-                {
-                    (&sel.start, &sel.end)
-                } else {
-                    (&sel.end, &sel.start)
-                };
-
-                // Is in selection if the line is bigger than start line or same line but char
-                // bigger than start char, and if the line is less than or equal to line end and character is
-                // less than the end character.
-                (abs_line > start.line
-                    || (abs_line == start.line && char_idx >= start.character - numbar_space))
-                    && (abs_line < end.line
-                        || (abs_line == end.line && char_idx < end.character - numbar_space))
-            } else {
-                false
+            let in_selection = match selection {
+                Some(sel) if sel.kind == SelectionKind::Line => {
+                    let (first, last, _, _) = sel.bounds(numbar_space);
+                    (first..=last).contains(&abs_line)
+                }
+                Some(sel) if sel.kind == SelectionKind::Block => block_columns
+                    .as_ref()
+                    .is_some_and(|columns| columns.contains(&col)),
+                Some(sel) => {
+                    // Normalize selection, even if it went backwards, so that it's always start < end.
+                    // Always take into account that for rendering, we need to sub numbar_space to
+                    // compare in terms of line length!!
+                    let (start, end) = if sel.start.line < sel.end.line
+                        || (sel.start.line == sel.end.line
+                            && sel.start.character - numbar_space
+                                <= sel.end.character - numbar_space)
+                    {
+                        (&sel.start, &sel.end)
+                    } else {
+                        (&sel.end, &sel.start)
+                    };
+
+                    // Is in selection if the line is bigger than start line or same line but char
+                    // bigger than start char, and if the line is less than or equal to line end and character is
+                    // less than the end character.
+                    (abs_line > start.line
+                        || (abs_line == start.line && col >= start.character - numbar_space))
+                        && (abs_line < end.line
+                            || (abs_line == end.line && col < end.character - numbar_space))
+                }
+                None => false,
+            };
+
+            let in_search_match = !search_matches.is_empty() && {
+                let char_pos = app.buffers[app.current_buf_index]
+                    .file_text
+                    .line_to_char(abs_line)
+                    + col;
+                search_matches.iter().any(|found| found.contains(&char_pos))
+            };
+
+            let in_diagnostic = !diagnostics.is_empty() && {
+                let buffer = &app.buffers[app.current_buf_index];
+                let position = buffer.to_lsp_position(&BufferPosition {
+                    line: abs_line,
+                    character: col + numbar_space,
+                });
+                diagnostics
+                    .iter()
+                    .any(|diag| diag.range.is_inside(position.line, position.character))
             };
 
-            let in_diagnostic = diagnostics
+            let in_cursor = cursors
                 .iter()
-                .any(|diag| diag.range.is_inside(abs_line, char_idx + numbar_space));
+                .any(|cursor| cursor.line == abs_line && cursor.character - numbar_space == col);
 
             let mut style = Style::default();
 
+            if in_search_match {
+                style = style.bg(AMBER.c700).fg(Color::Black);
+            }
+
             if in_selection {
                 style = style.bg(PURPLE.c900);
             }
@@ -111,12 +155,46 @@ pub fn ui(frame: &mut Frame, app: &App) {
             if in_diagnostic {
                 style = style.underlined();
             }
-            let styled_char = Span::styled(ch.to_string(), style);
+
+            // Extra cursors, one at the end of a line is drawn over its line break.
+            if in_cursor {
+                style = style.reversed();
+            }
+            let text = if in_cursor && text == "\n" {
+                String::from(" ")
+            } else {
+                text
+            };
+            let styled_char = Span::styled(text, style);
             spans.push(styled_char);
         }
 
-        styled_lines.push(Line::from(spans));
+        let abs_line = start_line + i;
+        if app.buffers[app.current_buf_index].settings.wrap {
+            let width = app.buffers[app.current_buf_index].wrap_width();
+            let rows = app.buffers[app.current_buf_index].line_rows(abs_line);
+            for row in 0..rows {
+                let start = (row * width).min(spans.len());
+                let end = ((row + 1) * width).min(spans.len());
+                styled_lines.push(Line::from(spans[start..end].to_vec()));
+                nums_of_lines.push(if row == 0 {
+                    abs_line.to_string()
+                } else {
+                    String::from("↪")
+                });
+            }
+        } else {
+            styled_lines.push(Line::from(spans));
+            nums_of_lines.push(abs_line.to_string());
+        }
     }
+    let viewport_height = app.buffers[app.current_buf_index].viewport_height;
+    styled_lines.truncate(viewport_height);
+    nums_of_lines.truncate(viewport_height);
+
+    // Render line numbers for only the visible lines
+    let numbar_text = Paragraph::new(nums_of_lines.join("\n")).style(Color::Rgb(164, 160, 232));
+    frame.render_widget(numbar_text, top_area[0]);
 
     let file_text = Paragraph::new(styled_lines).style(Color::Rgb(164, 160, 232));
 
@@ -128,7 +206,7 @@ pub fn ui(frame: &mut Frame, app: &App) {
     });
 
     // Handle different modes
-    if app.mode == Mode::Command {
+    if app.mode == Mode::Command || app.mode == Mode::Search || app.mode == Mode::Confirm {
         // Render editor content first
         frame.render_widget(file_text, editor_area_chunks[0]);
 
@@ -151,10 +229,20 @@ pub fn ui(frame: &mut Frame, app: &App) {
             ])
             .split(main_area);
 
+        let (prefix, title) = match app.mode {
+            Mode::Search if app.search_forward => ('/', "Search"),
+            Mode::Search => ('?', "Search backward"),
+            Mode::Confirm => (' ', "Replace this match?"),
+            _ => (':', "Command"),
+        };
+        let content = match app.mode {
+            Mode::Confirm => "y/n/a/q/l",
+            _ => app.command.as_deref().unwrap_or(""),
+        };
         let command_popup = CommandPopup::default()
-            .content(app.command.as_deref().unwrap_or(""))
+            .content(prefix, content)
             .style(Color::Rgb(164, 160, 232).into())
-            .title("Command")
+            .title(title)
             .title_style(Style::new().white().bold())
             .border_style(Color::Black.into());
         frame.render_widget(command_popup, popup_subareas[1]);
@@ -170,7 +258,7 @@ pub fn ui(frame: &mut Frame, app: &App) {
         // let command = app.command.clone();
         // let dbg_str = format!("MODE: {mode}\n CURRENT COMMAND: {command:#?}");
         // let diags = app.diagnostics.clone();
-        let dbg_str = format!("Nothing");
+        let dbg_str = "Nothing".to_string();
 
         let popup = DebugPopup::default()
             .content(&dbg_str)
@@ -192,7 +280,7 @@ pub fn ui(frame: &mut Frame, app: &App) {
     let status_bar_area_bg = Block::default().style(Style::default().bg(Color::Rgb(40, 30, 51)));
 
     // Status bar showing mode, file, cursor position
-    let mode = format!(
+    let mut mode = format!(
         "  {} Mode :: {}",
         app.mode,
         app.buffers[app.current_buf_index]
@@ -200,8 +288,22 @@ pub fn ui(frame: &mut Frame, app: &App) {
             .clone()
             .unwrap_or("New File".to_string())
     );
+    if let Some(name) = app.recording_macro {
+        mode.push_str(&format!(" :: recording @{name}"));
+    }
+    if !cursors.is_empty() {
+        mode.push_str(&format!(" :: {} cursors", cursors.len() + 1));
+    }
+    if app.buffers[app.current_buf_index].loading {
+        mode.push_str(" :: large file (loading)");
+    } else if app.buffers[app.current_buf_index].large_file {
+        mode.push_str(" :: large file");
+    }
+    // Keys typed so far for an unfinished command, e.g. "3d".
+    let pending_keys = format!("{}    ", app.pending_keys);
     let cursor_pos = format!(
-        "{}:{}",
+        "{}    {}:{}",
+        app.buffers[app.current_buf_index].file_format,
         app.buffers[app.current_buf_index].current_position.line,
         app.buffers[app.current_buf_index]
             .current_position
@@ -210,12 +312,14 @@ pub fn ui(frame: &mut Frame, app: &App) {
     );
     let area_width = editor_area_chunks[1].width as usize;
     let mode_width = mode.chars().count();
+    let pending_width = pending_keys.chars().count();
     let position_width = cursor_pos.chars().count();
-    let spacer_width = area_width.saturating_sub(mode_width + position_width);
+    let spacer_width = area_width.saturating_sub(mode_width + pending_width + position_width);
 
     let text = Line::from(vec![
         Span::raw(mode),
         Span::raw(" ".repeat(spacer_width)),
+        Span::raw(pending_keys),
         Span::raw(cursor_pos),
     ]);
     let sb_paragraph = Paragraph::new(text)